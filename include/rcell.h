@@ -0,0 +1,29 @@
+/* C interface to the rcell crate, built with the 'ffi' feature enabled. The handles are
+ * atomically reference counted and may be used from any thread, thread safety of the
+ * values themselves is up to the host. */
+#ifndef RCELL_H
+#define RCELL_H
+
+#include <stdbool.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct RCellHandle rcell_t;
+typedef struct RCellStrong rcell_strong_t;
+typedef void (*rcell_destructor_t)(void *);
+
+rcell_t *rcell_new(void *ptr, rcell_destructor_t destructor);
+bool rcell_retain(rcell_t *cell);
+void rcell_release(rcell_t *cell);
+rcell_strong_t *rcell_request(const rcell_t *cell);
+void *rcell_strong_get(const rcell_strong_t *strong);
+void rcell_strong_free(rcell_strong_t *strong);
+void rcell_free(rcell_t *cell);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* RCELL_H */
@@ -0,0 +1,227 @@
+//! Derive macros for the [rcell](https://docs.rs/rcell) crate.
+//!
+//! These are re-exported by rcell when its **derive** feature is enabled and should be used
+//! from there.
+
+use proc_macro::TokenStream;
+
+mod parse;
+use parse::{compile_error, generic_arg, Function, Struct};
+
+/// Generates `release_all(&mut self)` and `remove_all(&mut self)` methods which call
+/// `RCell::release()` respectively `RCell::remove()` on every `RCell` field of the struct.
+#[proc_macro_derive(Release)]
+pub fn derive_release(input: TokenStream) -> TokenStream {
+    let s = match Struct::parse(input) {
+        Ok(s) => s,
+        Err(err) => return compile_error(&err),
+    };
+
+    let mut release = String::new();
+    let mut remove = String::new();
+    for field in s.fields.iter().filter(|field| field.is_rcell()) {
+        release.push_str(&format!(
+            "::rcell::RCell::release(&mut self.{});",
+            field.member
+        ));
+        remove.push_str(&format!(
+            "::rcell::RCell::remove(&mut self.{});",
+            field.member
+        ));
+    }
+
+    format!(
+        "{header} {{
+            /// Releases all RCell members, see `RCell::release()`.
+            pub fn release_all(&mut self) {{ {release} }}
+
+            /// Removes all RCell members, see `RCell::remove()`.
+            pub fn remove_all(&mut self) {{ {remove} }}
+        }}",
+        header = s.impl_header(),
+    )
+    .parse()
+    .unwrap()
+}
+
+/// Generates `retain_all(&mut self) -> bool` which tries to retain every `RCell` field of the
+/// struct and returns 'true' when all of them are alive. `retain_all_failed(&mut self)` does
+/// the same but returns the names of the fields which could not be retained.
+#[proc_macro_derive(Retain)]
+pub fn derive_retain(input: TokenStream) -> TokenStream {
+    let s = match Struct::parse(input) {
+        Ok(s) => s,
+        Err(err) => return compile_error(&err),
+    };
+
+    let mut retain = String::new();
+    for field in s.fields.iter().filter(|field| field.is_rcell()) {
+        retain.push_str(&format!(
+            "if ::rcell::RCell::retain(&mut self.{member}).is_none() {{ failed.push({member:?}); }}",
+            member = field.member
+        ));
+    }
+
+    format!(
+        "{header} {{
+            /// Retains all RCell members, see `RCell::retain()`. Returns 'true' when all
+            /// members are alive.
+            pub fn retain_all(&mut self) -> bool {{
+                self.retain_all_failed().is_empty()
+            }}
+
+            /// Retains all RCell members, see `RCell::retain()`. Returns the names of the
+            /// members which could not be retained.
+            #[allow(unused_mut)]
+            pub fn retain_all_failed(&mut self) -> ::std::vec::Vec<&'static str> {{
+                let mut failed = ::std::vec::Vec::new();
+                {retain}
+                failed
+            }}
+        }}",
+        header = s.impl_header(),
+    )
+    .parse()
+    .unwrap()
+}
+
+/// Generates accessors for every field annotated with `#[rcell]`. For a field `foo: RCell<Foo>`
+/// these are:
+///
+/// * `get_foo(&self) -> Option<Strong<Foo>>`, see `RCell::request()`
+/// * `set_foo(&mut self, value: Foo)`, stores `value` as new strong reference
+/// * `release_foo(&mut self)`, see `RCell::release()`
+#[proc_macro_derive(Accessors, attributes(rcell))]
+pub fn derive_accessors(input: TokenStream) -> TokenStream {
+    let s = match Struct::parse(input) {
+        Ok(s) => s,
+        Err(err) => return compile_error(&err),
+    };
+
+    let mut accessors = String::new();
+    for field in s.fields.iter().filter(|field| field.has_attr("rcell")) {
+        let name = match &field.name {
+            Some(name) if field.is_rcell() => name,
+            Some(_) => return compile_error("#[rcell] fields must be of type RCell<T>"),
+            None => return compile_error("#[rcell] is only supported on named fields"),
+        };
+        accessors.push_str(&format!(
+            "/// Returns the value of `{name}` when it is alive, see `RCell::request()`.
+            pub fn get_{name}(&self) -> ::std::option::Option<::rcell::Strong<{inner}>> {{
+                ::rcell::RCell::request(&self.{name})
+            }}
+
+            /// Stores `value` as new strong reference in `{name}`.
+            pub fn set_{name}(&mut self, value: {inner}) {{
+                self.{name} = ::rcell::RCell::new(value);
+            }}
+
+            /// Releases `{name}`, see `RCell::release()`.
+            pub fn release_{name}(&mut self) {{
+                ::rcell::RCell::release(&mut self.{name});
+            }}
+            ",
+            inner = field.rcell_inner(),
+        ));
+    }
+
+    format!("{header} {{ {accessors} }}", header = s.impl_header())
+        .parse()
+        .unwrap()
+}
+
+/// Implements `rcell::VisitCells` for a struct. Every `RCell` field is visited, other fields
+/// are visited when they are annotated with `#[visit]` and their type implements
+/// `VisitCells` itself.
+#[proc_macro_derive(VisitCells, attributes(visit))]
+pub fn derive_visit_cells(input: TokenStream) -> TokenStream {
+    let s = match Struct::parse(input) {
+        Ok(s) => s,
+        Err(err) => return compile_error(&err),
+    };
+
+    let mut visit = String::new();
+    for field in s
+        .fields
+        .iter()
+        .filter(|field| field.is_rcell() || field.has_attr("visit"))
+    {
+        visit.push_str(&format!(
+            "::rcell::VisitCells::visit_cells(&mut self.{}, visitor);",
+            field.member
+        ));
+    }
+
+    format!(
+        "{header} {{
+            #[allow(unused_variables)]
+            fn visit_cells<V: ::rcell::CellVisitor + ?Sized>(&mut self, visitor: &mut V) {{
+                {visit}
+            }}
+        }}",
+        header = s.trait_impl_header("::rcell::VisitCells"),
+    )
+    .parse()
+    .unwrap()
+}
+
+/// Memoizes a function returning `Strong<T>`. The result is kept in a weak cell keyed by the
+/// function arguments and is only recomputed when the previous result got dropped.
+///
+/// Arguments must be `Clone + Hash + Eq`, reference arguments `&T` are stored as
+/// `<T as ToOwned>::Owned`. Generic functions and methods are not supported.
+#[proc_macro_attribute]
+pub fn cached(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        return compile_error("#[cached] takes no arguments");
+    }
+    let f = match Function::parse(item) {
+        Ok(f) => f,
+        Err(err) => return compile_error(&err),
+    };
+
+    let mut params = Vec::new();
+    let mut key_types = String::new();
+    let mut key_values = String::new();
+    for (pat, ty) in &f.params {
+        let ty_str = proc_macro::TokenStream::from_iter(ty.iter().cloned()).to_string();
+        params.push(format!("{pat}: {ty_str}"));
+        let name = pat
+            .clone()
+            .into_iter()
+            .last()
+            .map(|name| name.to_string())
+            .unwrap_or_default();
+        match ty.first() {
+            Some(proc_macro::TokenTree::Punct(p)) if p.as_char() == '&' => {
+                // skip an optional lifetime
+                let skip = match ty.get(1) {
+                    Some(proc_macro::TokenTree::Punct(p)) if p.as_char() == '\'' => 3,
+                    _ => 1,
+                };
+                let referent =
+                    proc_macro::TokenStream::from_iter(ty[skip..].iter().cloned()).to_string();
+                key_types.push_str(&format!("<{referent} as ::std::borrow::ToOwned>::Owned,"));
+                key_values.push_str(&format!("::std::borrow::ToOwned::to_owned({name}),"));
+            }
+            _ => {
+                key_types.push_str(&format!("{ty_str},"));
+                key_values.push_str(&format!("::std::clone::Clone::clone(&{name}),"));
+            }
+        }
+    }
+
+    let ret = proc_macro::TokenStream::from_iter(f.ret.iter().cloned()).to_string();
+    format!(
+        "{prefix} fn {name}({params}) -> {ret} {{
+            ::rcell::__memo!(({key_types}), {value}, ({key_values}), move || -> {ret} {body})
+        }}",
+        prefix = f.prefix,
+        name = f.name,
+        params = params.join(", "),
+        value = generic_arg(&f.ret),
+        body = f.body,
+    )
+    .parse()
+    .unwrap()
+}
@@ -0,0 +1,376 @@
+//! Minimal struct and function parser working on plain `proc_macro` tokens.
+
+use proc_macro::{Delimiter, Group, Ident, Spacing, TokenStream, TokenTree};
+
+/// The parts of a struct definition the derives need.
+pub struct Struct {
+    /// Name of the struct.
+    pub name: Ident,
+    /// Generic parameters with bounds, without defaults, for `impl<...>`.
+    pub impl_generics: String,
+    /// Generic arguments for `Name<...>`.
+    pub type_generics: String,
+    /// The where clause including the `where` keyword, may be empty.
+    pub where_clause: String,
+    /// The fields in declaration order.
+    pub fields: Vec<Field>,
+}
+
+/// A single struct field.
+pub struct Field {
+    /// Field accessor, the name for named fields or the index for tuple fields.
+    pub member: String,
+    /// The field name, `None` for tuple fields.
+    pub name: Option<Ident>,
+    /// The field type.
+    pub ty: Vec<TokenTree>,
+    /// The attributes (contents of `#[...]`) on this field.
+    pub attrs: Vec<Group>,
+}
+
+impl Struct {
+    /// Parses a struct definition as passed to a derive macro.
+    pub fn parse(input: TokenStream) -> Result<Struct, String> {
+        let mut tokens = input.into_iter().peekable();
+
+        // skip attributes and visibility up to the 'struct' keyword
+        loop {
+            match tokens.next() {
+                Some(TokenTree::Ident(ident)) if ident.to_string() == "struct" => break,
+                Some(TokenTree::Ident(ident))
+                    if ident.to_string() == "enum" || ident.to_string() == "union" =>
+                {
+                    return Err("rcell derives can only be used on structs".into())
+                }
+                Some(_) => {}
+                None => return Err("expected a struct".into()),
+            }
+        }
+
+        let name = match tokens.next() {
+            Some(TokenTree::Ident(ident)) => ident,
+            _ => return Err("expected the struct name".into()),
+        };
+
+        let mut generics = Vec::new();
+        if matches!(tokens.peek(), Some(TokenTree::Punct(p)) if p.as_char() == '<') {
+            tokens.next();
+            let mut depth = 1;
+            let mut prev_joint_dash = false;
+            for token in tokens.by_ref() {
+                if let TokenTree::Punct(p) = &token {
+                    match p.as_char() {
+                        '<' => depth += 1,
+                        '>' if !prev_joint_dash => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                        _ => {}
+                    }
+                    prev_joint_dash = p.as_char() == '-' && p.spacing() == Spacing::Joint;
+                } else {
+                    prev_joint_dash = false;
+                }
+                generics.push(token);
+            }
+        }
+
+        // named structs have the where clause before the body, tuple structs after it
+        let mut where_clause = Vec::new();
+        let mut body = None;
+        for token in tokens {
+            match token {
+                TokenTree::Group(group)
+                    if body.is_none()
+                        && (group.delimiter() == Delimiter::Brace
+                            || (group.delimiter() == Delimiter::Parenthesis
+                                && where_clause.is_empty())) =>
+                {
+                    body = Some(group)
+                }
+                TokenTree::Punct(p) if p.as_char() == ';' => {}
+                other => where_clause.push(other),
+            }
+        }
+        let body = body.ok_or("expected struct fields")?;
+
+        let (impl_generics, type_generics) = split_generics(generics);
+
+        Ok(Struct {
+            name,
+            impl_generics,
+            type_generics,
+            where_clause: TokenStream::from_iter(where_clause).to_string(),
+            fields: parse_fields(body),
+        })
+    }
+
+    /// Returns the header `impl<...> Name<...> where ...` for an inherent impl.
+    pub fn impl_header(&self) -> String {
+        self.trait_impl_header("")
+    }
+
+    /// Returns the header `impl<...> Trait for Name<...> where ...`, an empty `tr` gives an
+    /// inherent impl.
+    pub fn trait_impl_header(&self, tr: &str) -> String {
+        let tr = if tr.is_empty() {
+            String::new()
+        } else {
+            format!("{tr} for ")
+        };
+        format!(
+            "impl<{}> {}{}<{}> {}",
+            self.impl_generics, tr, self.name, self.type_generics, self.where_clause
+        )
+    }
+}
+
+impl Field {
+    /// Returns 'true' when the outermost type of this field is `RCell<...>`.
+    pub fn is_rcell(&self) -> bool {
+        let mut last = None;
+        for token in &self.ty {
+            match token {
+                TokenTree::Ident(ident) => last = Some(ident.to_string()),
+                TokenTree::Punct(p) if p.as_char() == '<' => break,
+                TokenTree::Punct(p) if p.as_char() == ':' => {}
+                _ => return false,
+            }
+        }
+        last.as_deref() == Some("RCell")
+    }
+
+    /// Returns the generic argument of a `RCell<...>` field type.
+    pub fn rcell_inner(&self) -> String {
+        generic_arg(&self.ty)
+    }
+
+    /// Returns 'true' when the field carries the attribute `#[name]`.
+    pub fn has_attr(&self, name: &str) -> bool {
+        self.attrs.iter().any(|attr| {
+            matches!(attr.stream().into_iter().next(),
+                     Some(TokenTree::Ident(ident)) if ident.to_string() == name)
+        })
+    }
+}
+
+/// A function definition as passed to an attribute macro.
+pub struct Function {
+    /// Attributes and visibility preceding the `fn` keyword.
+    pub prefix: TokenStream,
+    /// Name of the function.
+    pub name: Ident,
+    /// The parameters as (pattern, type).
+    pub params: Vec<(TokenStream, Vec<TokenTree>)>,
+    /// The return type.
+    pub ret: Vec<TokenTree>,
+    /// The function body.
+    pub body: Group,
+}
+
+impl Function {
+    /// Parses a plain (non generic) function definition.
+    pub fn parse(input: TokenStream) -> Result<Function, String> {
+        let mut tokens = input.into_iter().peekable();
+
+        let mut prefix = Vec::new();
+        loop {
+            match tokens.next() {
+                Some(TokenTree::Ident(ident)) if ident.to_string() == "fn" => break,
+                Some(token) => prefix.push(token),
+                None => return Err("expected a function".into()),
+            }
+        }
+
+        let name = match tokens.next() {
+            Some(TokenTree::Ident(ident)) => ident,
+            _ => return Err("expected the function name".into()),
+        };
+
+        let params = match tokens.next() {
+            Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Parenthesis => group,
+            _ => return Err("generic functions are not supported".into()),
+        };
+        let params = split_top_level(params.stream())
+            .into_iter()
+            .map(|param| {
+                let colon = param
+                    .iter()
+                    .position(|token| matches!(token, TokenTree::Punct(p) if p.as_char() == ':'))
+                    .ok_or("methods are not supported")?;
+                Ok((
+                    TokenStream::from_iter(param[..colon].iter().cloned()),
+                    param[colon + 1..].to_vec(),
+                ))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let mut ret = Vec::new();
+        let mut body = None;
+        for token in tokens {
+            match token {
+                TokenTree::Group(group) if group.delimiter() == Delimiter::Brace => {
+                    body = Some(group);
+                    break;
+                }
+                TokenTree::Ident(ident) if ident.to_string() == "where" => {
+                    return Err("where clauses are not supported".into())
+                }
+                other => ret.push(other),
+            }
+        }
+        // strip the '->'
+        if ret.len() < 2 {
+            return Err("expected a return type".into());
+        }
+        ret.drain(..2);
+
+        Ok(Function {
+            prefix: TokenStream::from_iter(prefix),
+            name,
+            params,
+            ret,
+            body: body.ok_or("expected a function body")?,
+        })
+    }
+}
+
+/// Returns the generic argument of the outermost type in `ty`, for example `T` of `RCell<T>`.
+pub fn generic_arg(ty: &[TokenTree]) -> String {
+    let mut inner = Vec::new();
+    let mut depth = 0;
+    for token in ty {
+        match token {
+            TokenTree::Punct(p) if p.as_char() == '<' => {
+                depth += 1;
+                if depth == 1 {
+                    continue;
+                }
+            }
+            TokenTree::Punct(p) if p.as_char() == '>' => {
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+            _ => {}
+        }
+        if depth > 0 {
+            inner.push(token.clone());
+        }
+    }
+    TokenStream::from_iter(inner).to_string()
+}
+
+/// Splits a comma separated token list at the top level, angle brackets are tracked.
+pub fn split_top_level(tokens: impl IntoIterator<Item = TokenTree>) -> Vec<Vec<TokenTree>> {
+    let mut parts = vec![Vec::new()];
+    let mut depth = 0;
+    let mut prev_joint_dash = false;
+    for token in tokens {
+        if let TokenTree::Punct(p) = &token {
+            match p.as_char() {
+                '<' => depth += 1,
+                '>' if !prev_joint_dash => depth -= 1,
+                ',' if depth == 0 => {
+                    parts.push(Vec::new());
+                    prev_joint_dash = false;
+                    continue;
+                }
+                _ => {}
+            }
+            prev_joint_dash = p.as_char() == '-' && p.spacing() == Spacing::Joint;
+        } else {
+            prev_joint_dash = false;
+        }
+        parts.last_mut().unwrap().push(token);
+    }
+    parts.retain(|part| !part.is_empty());
+    parts
+}
+
+fn split_generics(generics: Vec<TokenTree>) -> (String, String) {
+    let mut impl_generics = Vec::new();
+    let mut type_generics = Vec::new();
+    for param in split_top_level(generics) {
+        // strip defaults, they are not allowed on impls
+        let mut bounded = Vec::new();
+        let mut depth = 0;
+        for token in &param {
+            if let TokenTree::Punct(p) = token {
+                match p.as_char() {
+                    '<' => depth += 1,
+                    '>' => depth -= 1,
+                    '=' if depth == 0 => break,
+                    _ => {}
+                }
+            }
+            bounded.push(token.clone());
+        }
+        impl_generics.push(TokenStream::from_iter(bounded).to_string());
+
+        let name = match (&param[0], param.get(1)) {
+            (TokenTree::Punct(p), Some(lifetime)) if p.as_char() == '\'' => {
+                format!("'{lifetime}")
+            }
+            (TokenTree::Ident(ident), Some(name)) if ident.to_string() == "const" => {
+                name.to_string()
+            }
+            (first, _) => first.to_string(),
+        };
+        type_generics.push(name);
+    }
+    (impl_generics.join(", "), type_generics.join(", "))
+}
+
+fn parse_fields(body: Group) -> Vec<Field> {
+    let named = body.delimiter() == Delimiter::Brace;
+    split_top_level(body.stream())
+        .into_iter()
+        .enumerate()
+        .map(|(index, tokens)| {
+            let mut tokens = tokens.into_iter().peekable();
+            let mut attrs = Vec::new();
+            while matches!(tokens.peek(), Some(TokenTree::Punct(p)) if p.as_char() == '#') {
+                tokens.next();
+                if let Some(TokenTree::Group(group)) = tokens.next() {
+                    attrs.push(group);
+                }
+            }
+            if matches!(tokens.peek(), Some(TokenTree::Ident(ident)) if ident.to_string() == "pub")
+            {
+                tokens.next();
+                if matches!(tokens.peek(), Some(TokenTree::Group(group))
+                            if group.delimiter() == Delimiter::Parenthesis)
+                {
+                    tokens.next();
+                }
+            }
+            let name = if named {
+                let name = match tokens.next() {
+                    Some(TokenTree::Ident(ident)) => Some(ident),
+                    _ => None,
+                };
+                tokens.next(); // the ':'
+                name
+            } else {
+                None
+            };
+            Field {
+                member: name
+                    .as_ref()
+                    .map_or_else(|| index.to_string(), ToString::to_string),
+                name,
+                ty: tokens.collect(),
+                attrs,
+            }
+        })
+        .collect()
+}
+
+/// Turns an error message into a `compile_error!` invocation.
+pub fn compile_error(message: &str) -> TokenStream {
+    format!("compile_error!({message:?});").parse().unwrap()
+}
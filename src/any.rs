@@ -0,0 +1,129 @@
+//! Type erased RCell.
+
+use std::any::Any;
+use std::ops::{Deref, DerefMut};
+
+use crate::{RCell, Strong, Weak};
+
+/// The type erased value stored in an `AnyRCell`, `dyn Any + Send + Sync` with the **sync**
+/// feature, `dyn Any` otherwise.
+#[cfg(feature = "sync")]
+pub type DynAny = dyn Any + Send + Sync;
+/// The type erased value stored in an `AnyRCell`, `dyn Any + Send + Sync` with the **sync**
+/// feature, `dyn Any` otherwise.
+#[cfg(not(feature = "sync"))]
+pub type DynAny = dyn Any;
+
+/// Values which can be stored in an `AnyRCell`, any `'static + Send + Sync` type with the
+/// **sync** feature, any `'static` type otherwise.
+#[cfg(feature = "sync")]
+pub trait AnyValue: Any + Send + Sync {}
+#[cfg(feature = "sync")]
+impl<T: Any + Send + Sync> AnyValue for T {}
+
+/// Values which can be stored in an `AnyRCell`, any `'static + Send + Sync` type with the
+/// **sync** feature, any `'static` type otherwise.
+#[cfg(not(feature = "sync"))]
+pub trait AnyValue: Any {}
+#[cfg(not(feature = "sync"))]
+impl<T: Any> AnyValue for T {}
+
+/// A type erased RCell, cells of different types can be stored in one container. The value
+/// is recovered with `downcast_request()`. Derefs to `RCell<DynAny>` for the usual
+/// operations.
+#[derive(Debug, Default)]
+pub struct AnyRCell(RCell<DynAny>);
+
+impl AnyRCell {
+    /// Creates a new strong AnyRCell from the supplied value.
+    pub fn new<T: AnyValue>(value: T) -> Self {
+        Self::from_strong(Strong::new(value))
+    }
+
+    /// Creates a new strong AnyRCell from the supplied `Strong<T>`.
+    pub fn from_strong<T: AnyValue>(strong: Strong<T>) -> Self {
+        let strong: Strong<DynAny> = strong;
+        AnyRCell(RCell::from(strong))
+    }
+
+    /// Creates a new weak AnyRCell from the supplied `Weak<T>`.
+    pub fn from_weak<T: AnyValue>(weak: Weak<T>) -> Self {
+        let weak: Weak<DynAny> = weak;
+        AnyRCell(RCell::from(weak))
+    }
+
+    /// Returns 'true' when the value is alive and of type `T`.
+    pub fn is<T: AnyValue>(&self) -> bool {
+        self.0.request().is_some_and(|strong| strong.is::<T>())
+    }
+
+    /// Tries to get a `Strong<T>` from the cell. Fails when the value is not alive or not of
+    /// type `T`.
+    pub fn downcast_request<T: AnyValue>(&self) -> Option<Strong<T>> {
+        self.0.request()?.downcast::<T>().ok()
+    }
+
+    /// Like `downcast_request()` but retains the cell on success, see `RCell::retain()`.
+    pub fn downcast_retain<T: AnyValue>(&mut self) -> Option<Strong<T>> {
+        if !self.is::<T>() {
+            return None;
+        }
+        self.0.retain()?.downcast::<T>().ok()
+    }
+}
+
+impl Deref for AnyRCell {
+    type Target = RCell<DynAny>;
+
+    fn deref(&self) -> &RCell<DynAny> {
+        &self.0
+    }
+}
+
+impl DerefMut for AnyRCell {
+    fn deref_mut(&mut self) -> &mut RCell<DynAny> {
+        &mut self.0
+    }
+}
+
+impl<T: AnyValue> From<Strong<T>> for AnyRCell {
+    fn from(strong: Strong<T>) -> Self {
+        AnyRCell::from_strong(strong)
+    }
+}
+
+impl<T: AnyValue> From<Weak<T>> for AnyRCell {
+    fn from(weak: Weak<T>) -> Self {
+        AnyRCell::from_weak(weak)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AnyRCell;
+    use crate::Strong;
+
+    #[test]
+    fn downcast() {
+        let cells = [AnyRCell::new(1u32), AnyRCell::new("foo")];
+        assert!(cells[0].is::<u32>());
+        assert!(!cells[0].is::<u64>());
+        assert_eq!(*cells[0].downcast_request::<u32>().unwrap(), 1);
+        assert_eq!(cells[0].downcast_request::<&str>(), None);
+        assert_eq!(*cells[1].downcast_request::<&str>().unwrap(), "foo");
+    }
+
+    #[test]
+    fn weak() {
+        let strong = Strong::new(String::from("foo"));
+        let mut rcell = AnyRCell::from(Strong::downgrade(&strong));
+        assert!(!rcell.retained());
+        assert_eq!(rcell.downcast_retain::<u8>(), None);
+        assert!(!rcell.retained());
+        assert_eq!(*rcell.downcast_retain::<String>().unwrap(), "foo");
+        assert!(rcell.retained());
+        drop(strong);
+        rcell.release();
+        assert_eq!(rcell.downcast_request::<String>(), None);
+    }
+}
@@ -0,0 +1,207 @@
+//! Asset cache with hot reloading.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::{RCellCache, Strong, VersionedRCell};
+
+/// Handle to an asset of an `AssetCache`. The asset may be reloaded in place, handles always
+/// see the current value and can check its version to notice reloads.
+#[derive(Debug)]
+pub struct Asset<T> {
+    slot: Strong<Mutex<VersionedRCell<T>>>,
+}
+
+impl<T> Clone for Asset<T> {
+    fn clone(&self) -> Self {
+        Asset {
+            slot: self.slot.clone(),
+        }
+    }
+}
+
+impl<T> Asset<T> {
+    fn lock(&self) -> MutexGuard<'_, VersionedRCell<T>> {
+        self.slot
+            .lock()
+            .unwrap_or_else(|poison| poison.into_inner())
+    }
+
+    /// Returns the current value.
+    pub fn get(&self) -> Strong<T> {
+        self.get_versioned().0
+    }
+
+    /// Returns the current value together with its version.
+    pub fn get_versioned(&self) -> (Strong<T>, u64) {
+        self.lock()
+            .request_versioned()
+            .expect("assets are always retained")
+    }
+
+    /// Returns the version, it increases with every reload.
+    pub fn version(&self) -> u64 {
+        self.lock().version()
+    }
+}
+
+/// Listener called with the key and the new value after an asset got reloaded.
+pub type ReloadHook<K, T> = Box<dyn FnMut(&K, &Strong<T>) + Send>;
+
+/// A keyed cache of assets (textures, sounds, configs) loaded on demand by a blocking loader.
+/// At most `capacity` assets are retained, the others stay available as long as handles to
+/// them exist. Pinned assets stay loaded regardless of the capacity until unpinned as often
+/// as they were pinned. `reload()` swaps an asset in place, existing handles see the new value.
+///
+/// Only blocking loading is supported, `get()`, `pin()` and `reload()` run the loader on the
+/// calling thread. Async code should call them from a blocking task or load through an
+/// `AsyncRCell` instead.
+pub struct AssetCache<K, T, F> {
+    loader: F,
+    cache: RCellCache<K, Mutex<VersionedRCell<T>>>,
+    pinned: HashMap<K, (usize, Asset<T>)>,
+    hooks: Vec<ReloadHook<K, T>>,
+}
+
+impl<K: std::fmt::Debug, T: std::fmt::Debug, F> std::fmt::Debug for AssetCache<K, T, F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AssetCache")
+            .field("cache", &self.cache)
+            .field("pinned", &self.pinned)
+            .field("hooks", &self.hooks.len())
+            .finish()
+    }
+}
+
+impl<K: Eq + Hash + Clone, T, F: Fn(&K) -> T> AssetCache<K, T, F> {
+    /// Creates a cache retaining at most `capacity` unpinned assets, loading them with the
+    /// blocking `loader`.
+    pub fn new(capacity: usize, loader: F) -> Self {
+        AssetCache {
+            loader,
+            cache: RCellCache::new(capacity),
+            pinned: HashMap::new(),
+            hooks: Vec::new(),
+        }
+    }
+
+    /// Returns the number of assets, including released ones which may be gone.
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// Returns 'true' when there are no assets.
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+
+    /// Returns the asset for `key`, loads it when it is not alive.
+    pub fn get(&mut self, key: &K) -> Asset<T> {
+        let loader = &self.loader;
+        let slot = self
+            .cache
+            .get_or_insert_with(key.clone(), || Mutex::new(VersionedRCell::new(loader(key))));
+        Asset { slot }
+    }
+
+    /// Returns the asset for `key` without loading it.
+    pub fn peek(&self, key: &K) -> Option<Asset<T>> {
+        self.cache.peek(key).map(|slot| Asset { slot })
+    }
+
+    /// Loads `key` and keeps it loaded until `unpin()` was called as often as `pin()`.
+    pub fn pin(&mut self, key: &K) -> Asset<T> {
+        let asset = self.get(key);
+        self.pinned
+            .entry(key.clone())
+            .or_insert_with(|| (0, asset.clone()))
+            .0 += 1;
+        asset
+    }
+
+    /// Drops one pin of `key`. Returns 'true' when the asset was pinned.
+    pub fn unpin(&mut self, key: &K) -> bool {
+        match self.pinned.get_mut(key) {
+            Some((1, _)) => {
+                self.pinned.remove(key);
+                true
+            }
+            Some((count, _)) => {
+                *count -= 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns how often `key` is pinned.
+    pub fn pin_count(&self, key: &K) -> usize {
+        self.pinned.get(key).map_or(0, |(count, _)| *count)
+    }
+
+    /// Adds a hook called after an asset got reloaded.
+    pub fn add_reload_hook(&mut self, hook: impl FnMut(&K, &Strong<T>) + Send + 'static) {
+        self.hooks.push(Box::new(hook));
+    }
+
+    /// Reloads an asset which is alive and swaps the new value in place. Returns the new
+    /// version or 'None' when the asset is not loaded.
+    pub fn reload(&mut self, key: &K) -> Option<u64> {
+        let asset = self.peek(key)?;
+        let value = Strong::new((self.loader)(key));
+        let version = asset.lock().set_strong(value.clone());
+        for hook in &mut self.hooks {
+            hook(key, &value);
+        }
+        Some(version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::AssetCache;
+
+    #[test]
+    fn load_and_reload() {
+        let loads = Arc::new(AtomicUsize::new(0));
+        let counter = loads.clone();
+        let mut cache = AssetCache::new(1, move |path: &&str| {
+            format!("{path}#{}", counter.fetch_add(1, Ordering::SeqCst))
+        });
+        let reloaded = Arc::new(AtomicUsize::new(0));
+        let hook_count = reloaded.clone();
+        cache.add_reload_hook(move |_, _| {
+            hook_count.fetch_add(1, Ordering::SeqCst);
+        });
+
+        let texture = cache.get(&"texture");
+        assert_eq!(*texture.get(), "texture#0");
+        assert_eq!(*cache.get(&"texture").get(), "texture#0");
+        let (_, version) = texture.get_versioned();
+
+        assert_eq!(cache.reload(&"texture"), Some(1));
+        assert_ne!(texture.version(), version);
+        assert_eq!(*texture.get(), "texture#1");
+        assert_eq!(reloaded.load(Ordering::SeqCst), 1);
+        assert_eq!(cache.reload(&"missing"), None);
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn pinning() {
+        let mut cache = AssetCache::new(0, |key: &u32| *key * 10);
+        cache.pin(&1);
+        cache.pin(&1);
+        assert_eq!(cache.pin_count(&1), 2);
+        assert_eq!(*cache.peek(&1).unwrap().get(), 10);
+        assert!(cache.unpin(&1));
+        assert!(cache.peek(&1).is_some());
+        assert!(cache.unpin(&1));
+        assert!(cache.peek(&1).is_none());
+        assert!(!cache.unpin(&1));
+    }
+}
@@ -0,0 +1,290 @@
+//! RCell for async code.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+
+use crate::{RCell, Replace, Strong};
+
+#[derive(Debug)]
+struct Slot<T> {
+    cell: RCell<T>,
+    loading: bool,
+    waiters: Vec<Waker>,
+}
+
+/// Produces the futures loading the value of an `AsyncRCell`. Implemented for closures
+/// returning a future, such as `|| async { load().await }`.
+pub trait AsyncLoader<T> {
+    /// The future returned by `load()`.
+    type Future: Future<Output = T>;
+
+    /// Starts loading the value.
+    fn load(&self) -> Self::Future;
+}
+
+impl<T, F, Fut> AsyncLoader<T> for F
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = T>,
+{
+    type Future = Fut;
+
+    fn load(&self) -> Fut {
+        self()
+    }
+}
+
+/// A RCell which can be filled by a future. Concurrent callers missing on the value wait for
+/// the loader which is already in flight instead of running their own, this makes it usable
+/// as an async memo slot. Only depends on `std::future`, it works with any executor.
+///
+/// When constructed `with_loader()` the cell fetches its value on `request()` whenever it is
+/// not alive, for example from disk or network.
+#[derive(Debug)]
+pub struct AsyncRCell<T, L = ()> {
+    slot: Mutex<Slot<T>>,
+    loader: L,
+}
+
+impl<T> AsyncRCell<T> {
+    /// Creates an empty AsyncRCell.
+    pub fn new() -> Self {
+        Self::from_rcell(RCell::empty())
+    }
+
+    /// Wraps an existing RCell.
+    pub fn from_rcell(cell: RCell<T>) -> Self {
+        Self::from_rcell_with_loader(cell, ())
+    }
+}
+
+impl<T, L: AsyncLoader<T>> AsyncRCell<T, L> {
+    /// Creates an empty AsyncRCell which loads its value with `loader`.
+    pub fn with_loader(loader: L) -> Self {
+        Self::from_rcell_with_loader(RCell::empty(), loader)
+    }
+
+    /// Returns the value when it is alive, otherwise loads and retains it. Concurrent
+    /// requests share a single load.
+    pub async fn request(&self) -> Strong<T> {
+        self.get_or_load(|| self.loader.load()).await
+    }
+}
+
+impl<T, L> AsyncRCell<T, L> {
+    /// Wraps an existing RCell, `loader` is used when its value is not alive.
+    pub fn from_rcell_with_loader(cell: RCell<T>, loader: L) -> Self {
+        AsyncRCell {
+            slot: Mutex::new(Slot {
+                cell,
+                loading: false,
+                waiters: Vec::new(),
+            }),
+            loader,
+        }
+    }
+
+    /// Returns the loader.
+    pub fn loader(&self) -> &L {
+        &self.loader
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Slot<T>> {
+        self.slot
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Returns the value when it is alive, without loading it.
+    pub fn get(&self) -> Option<Strong<T>> {
+        self.lock().cell.request()
+    }
+
+    /// Returns 'true' when a loader is in flight.
+    pub fn is_loading(&self) -> bool {
+        self.lock().loading
+    }
+
+    /// Releases the cell, see `RCell::release()`.
+    pub fn release(&self) {
+        self.lock().cell.release();
+    }
+
+    /// Removes the reference from the cell, see `RCell::remove()`.
+    pub fn remove(&self) {
+        self.lock().cell.remove();
+    }
+
+    /// Returns the value when it is alive, otherwise awaits `f` and stores its result as
+    /// strong reference. When another caller is already loading the value this waits for it
+    /// instead and `f` is dropped unpolled. Should the loading caller be cancelled or panic,
+    /// one of the waiting callers runs its loader.
+    pub async fn get_or_insert_with(&self, f: impl Future<Output = T>) -> Strong<T> {
+        self.get_or_load(|| f).await
+    }
+
+    /// Like `get_or_insert_with()` but only creates the loader future on a miss.
+    async fn get_or_load<F: Future<Output = T>>(&self, f: impl FnOnce() -> F) -> Strong<T> {
+        loop {
+            {
+                let mut slot = self.lock();
+                if let Some(strong) = slot.cell.retain() {
+                    return strong;
+                }
+                if !slot.loading {
+                    slot.loading = true;
+                    break;
+                }
+            }
+            WaitLoaded { cell: self }.await;
+        }
+
+        let guard = LoadGuard { cell: self };
+        let strong = Strong::new(f().await);
+        guard.cell.lock().cell.replace(strong.clone());
+        strong
+    }
+}
+
+impl<T> Default for AsyncRCell<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Ends loading and wakes the waiters, also when the loader gets cancelled or panics.
+struct LoadGuard<'a, T, L> {
+    cell: &'a AsyncRCell<T, L>,
+}
+
+impl<T, L> Drop for LoadGuard<'_, T, L> {
+    fn drop(&mut self) {
+        let mut slot = self.cell.lock();
+        slot.loading = false;
+        for waker in slot.waiters.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+/// Waits until no loader is in flight.
+struct WaitLoaded<'a, T, L> {
+    cell: &'a AsyncRCell<T, L>,
+}
+
+impl<T, L> Future for WaitLoaded<'_, T, L> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut slot = self.cell.lock();
+        if !slot.loading {
+            return Poll::Ready(());
+        }
+        if !slot.waiters.iter().any(|waker| waker.will_wake(cx.waker())) {
+            slot.waiters.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::pin::{pin, Pin};
+    use std::task::{Context, Poll, Waker};
+
+    use super::AsyncRCell;
+
+    /// Polls a future to completion, busy looping on a noop waker.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+    }
+
+    /// Returns pending once.
+    #[derive(Default)]
+    struct YieldOnce(bool);
+
+    impl Future for YieldOnce {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 {
+                Poll::Ready(())
+            } else {
+                self.0 = true;
+                Poll::Pending
+            }
+        }
+    }
+
+    #[test]
+    fn memo() {
+        let cell = AsyncRCell::new();
+        assert_eq!(cell.get(), None);
+        assert_eq!(*block_on(cell.get_or_insert_with(async { 1 })), 1);
+        assert_eq!(*block_on(cell.get_or_insert_with(async { 2 })), 1);
+        cell.remove();
+        assert_eq!(*block_on(cell.get_or_insert_with(async { 3 })), 3);
+    }
+
+    #[test]
+    fn singleflight() {
+        let cell = AsyncRCell::new();
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut first = pin!(cell.get_or_insert_with(async {
+            YieldOnce::default().await;
+            1
+        }));
+        let mut second = pin!(cell.get_or_insert_with(async { 2 }));
+
+        assert!(first.as_mut().poll(&mut cx).is_pending());
+        assert!(cell.is_loading());
+        assert!(second.as_mut().poll(&mut cx).is_pending());
+        assert!(matches!(first.as_mut().poll(&mut cx), Poll::Ready(value) if *value == 1));
+        assert!(matches!(second.as_mut().poll(&mut cx), Poll::Ready(value) if *value == 1));
+    }
+
+    #[test]
+    fn cancelled() {
+        let cell = AsyncRCell::new();
+        let mut cx = Context::from_waker(Waker::noop());
+        {
+            let mut first = pin!(cell.get_or_insert_with(async {
+                YieldOnce::default().await;
+                1
+            }));
+            assert!(first.as_mut().poll(&mut cx).is_pending());
+        }
+        assert!(!cell.is_loading());
+        assert_eq!(*block_on(cell.get_or_insert_with(async { 2 })), 2);
+    }
+
+    #[test]
+    fn loader() {
+        use std::cell::Cell;
+
+        let loads = Cell::new(0);
+        let cell = AsyncRCell::with_loader(|| {
+            loads.set(loads.get() + 1);
+            async { String::from("loaded") }
+        });
+        let value = block_on(cell.request());
+        assert_eq!(*value, "loaded");
+        cell.release();
+        assert_eq!(block_on(cell.request()), value);
+        assert_eq!(loads.get(), 1);
+
+        drop(value);
+        cell.release();
+        assert_eq!(*block_on(cell.request()), "loaded");
+        assert_eq!(loads.get(), 2);
+    }
+}
@@ -0,0 +1,243 @@
+//! Global memory budget over registered cells.
+
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+
+use crate::{CostOf, RCell, Retainable};
+
+/// Which entries a `MemoryBudget` releases first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BudgetOrder {
+    /// Entries with the highest cost first, on equal cost the oldest registration.
+    #[default]
+    Largest,
+    /// The oldest registrations first.
+    Oldest,
+}
+
+type Target = Mutex<dyn Retainable + Send>;
+
+#[derive(Debug)]
+struct Entry {
+    target: Weak<Target>,
+    cost: usize,
+    seq: u64,
+}
+
+#[derive(Debug)]
+struct Entries {
+    next_seq: u64,
+    entries: Vec<Entry>,
+}
+
+/// Keeps the estimated memory held by registered cells within a single budget. Cells (or
+/// anything `Retainable`) register with an estimate of the bytes they keep alive while
+/// retained. When the sum for the retained ones exceeds the budget, entries are released in
+/// `BudgetOrder` until it fits again.
+///
+/// The budget is enforced on registration and on `enforce()`, cells retained by other means
+/// are only noticed then.
+#[derive(Debug)]
+pub struct MemoryBudget {
+    budget: Mutex<usize>,
+    order: BudgetOrder,
+    entries: Mutex<Entries>,
+}
+
+impl MemoryBudget {
+    /// Creates a budget of `budget` bytes.
+    pub fn new(budget: usize, order: BudgetOrder) -> Self {
+        MemoryBudget {
+            budget: Mutex::new(budget),
+            order,
+            entries: Mutex::new(Entries {
+                next_seq: 0,
+                entries: Vec::new(),
+            }),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Entries> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|poison| poison.into_inner())
+    }
+
+    fn lock_target(
+        target: &Mutex<dyn Retainable + Send>,
+    ) -> MutexGuard<'_, dyn Retainable + Send + 'static> {
+        target.lock().unwrap_or_else(|poison| poison.into_inner())
+    }
+
+    /// Returns the budget in bytes.
+    pub fn budget(&self) -> usize {
+        *self
+            .budget
+            .lock()
+            .unwrap_or_else(|poison| poison.into_inner())
+    }
+
+    /// Changes the budget and enforces it. Returns the number of entries released.
+    pub fn set_budget(&self, budget: usize) -> usize {
+        *self
+            .budget
+            .lock()
+            .unwrap_or_else(|poison| poison.into_inner()) = budget;
+        self.enforce()
+    }
+
+    /// Registers a target which keeps about `cost` bytes alive while retained and enforces
+    /// the budget. Only a weak reference is kept, targets which got dropped are removed
+    /// automatically. Returns the number of entries released.
+    pub fn register<R: Retainable + Send + 'static>(
+        &self,
+        target: &Arc<Mutex<R>>,
+        cost: usize,
+    ) -> usize {
+        let target: Arc<Mutex<dyn Retainable + Send>> = target.clone();
+        let mut entries = self.lock();
+        let seq = entries.next_seq;
+        entries.next_seq += 1;
+        entries.entries.push(Entry {
+            target: Arc::downgrade(&target),
+            cost,
+            seq,
+        });
+        drop(entries);
+        self.enforce()
+    }
+
+    /// Registers a RCell with the cost of its current value, see `CostOf`.
+    pub fn register_cell<T: CostOf + Send + Sync + ?Sized + 'static>(
+        &self,
+        cell: &Arc<Mutex<RCell<T>>>,
+    ) -> usize {
+        let cost = cell
+            .lock()
+            .unwrap_or_else(|poison| poison.into_inner())
+            .request()
+            .map_or(0, |value| value.cost_of());
+        self.register(cell, cost)
+    }
+
+    /// Returns the number of registered targets which are still alive.
+    pub fn len(&self) -> usize {
+        self.lock()
+            .entries
+            .iter()
+            .filter(|entry| entry.target.strong_count() > 0)
+            .count()
+    }
+
+    /// Returns 'true' when no live targets are registered.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes the dropped targets and returns the live ones with their cost and sequence
+    /// number. The targets are locked by the callers only after our own lock is dropped, a
+    /// target may call back into the budget.
+    fn live(&self) -> Vec<(usize, u64, Arc<Target>)> {
+        let mut live = Vec::new();
+        self.lock()
+            .entries
+            .retain(|entry| match entry.target.upgrade() {
+                Some(target) => {
+                    live.push((entry.cost, entry.seq, target));
+                    true
+                }
+                None => false,
+            });
+        live
+    }
+
+    /// Returns the summed cost of the retained targets.
+    pub fn used(&self) -> usize {
+        self.live()
+            .into_iter()
+            .filter(|(_, _, target)| Self::lock_target(target).retained())
+            .map(|(cost, _, _)| cost)
+            .sum()
+    }
+
+    /// Releases retained targets in `BudgetOrder` until their summed cost fits into the
+    /// budget. Returns the number of targets released.
+    pub fn enforce(&self) -> usize {
+        let budget = self.budget();
+        let mut retained: Vec<_> = self
+            .live()
+            .into_iter()
+            .filter(|(_, _, target)| Self::lock_target(target).retained())
+            .collect();
+        let mut used: usize = retained.iter().map(|(cost, _, _)| cost).sum();
+        if used <= budget {
+            return 0;
+        }
+        match self.order {
+            BudgetOrder::Largest => {
+                retained.sort_by(|(a, a_seq, _), (b, b_seq, _)| b.cmp(a).then(a_seq.cmp(b_seq)))
+            }
+            BudgetOrder::Oldest => retained.sort_by_key(|(_, seq, _)| *seq),
+        }
+
+        let mut released = 0;
+        for (cost, _, target) in retained {
+            if used <= budget {
+                break;
+            }
+            Self::lock_target(&target).release();
+            used -= cost;
+            released += 1;
+        }
+        released
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::{BudgetOrder, MemoryBudget};
+    use crate::RCell;
+
+    fn cell(size: usize) -> Arc<Mutex<RCell<Vec<u8>>>> {
+        Arc::new(Mutex::new(RCell::new(vec![0; size])))
+    }
+
+    #[test]
+    fn largest() {
+        let budget = MemoryBudget::new(100, BudgetOrder::Largest);
+        let (small, large, medium) = (cell(10), cell(80), cell(40));
+        assert_eq!(budget.register(&small, 10), 0);
+        assert_eq!(budget.register(&large, 80), 0);
+        assert_eq!(budget.register(&medium, 40), 1);
+        assert!(!large.lock().unwrap().retained());
+        assert_eq!(budget.used(), 50);
+
+        large.lock().unwrap().remove();
+        drop(large);
+        assert_eq!(budget.len(), 2);
+        assert_eq!(budget.set_budget(20), 1);
+        assert!(small.lock().unwrap().retained());
+        assert_eq!(budget.used(), 10);
+    }
+
+    #[test]
+    fn oldest() {
+        let budget = MemoryBudget::new(100, BudgetOrder::Oldest);
+        let cells: Vec<_> = (0..4).map(|_| cell(40)).collect();
+        for cell in &cells {
+            budget.register(cell, 40);
+        }
+        let retained: Vec<_> = cells
+            .iter()
+            .map(|cell| cell.lock().unwrap().retained())
+            .collect();
+        assert_eq!(retained, vec![false, false, true, true]);
+
+        assert_eq!(budget.used(), 80);
+
+        let cell = Arc::new(Mutex::new(RCell::new(1u64)));
+        assert_eq!(budget.register_cell(&cell), 0);
+        assert_eq!(budget.used(), 88);
+    }
+}
@@ -0,0 +1,100 @@
+//! Identity based comparison of cells.
+
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+
+use crate::{RCell, Strong, Weak};
+
+/// Wraps a `RCell<T>`, `Strong<T>` or `Weak<T>` to compare and hash it by the address of its
+/// allocation instead of by value. This allows identity sets and maps of cached objects
+/// without requiring `T: Hash`. Empty cells are all equal. A weak reference keeps the
+/// allocation, so its address can't be reused even after the value got dropped: a `ByPtr`
+/// holding a `Weak<T>` or a weak cell keeps its identity. Only an empty cell, which holds no
+/// reference, loses track of the allocation.
+#[derive(Debug, Clone, Default)]
+pub struct ByPtr<C>(pub C);
+
+impl<C> ByPtr<C> {
+    /// Unwraps the inner cell or reference.
+    pub fn into_inner(self) -> C {
+        self.0
+    }
+}
+
+impl<C> Deref for ByPtr<C> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        &self.0
+    }
+}
+
+macro_rules! by_ptr {
+    ($type:ident, $addr:expr) => {
+        impl<T: ?Sized> ByPtr<$type<T>> {
+            /// Returns the address used for comparison.
+            pub fn addr(&self) -> usize {
+                $addr(&self.0)
+            }
+        }
+
+        impl<T: ?Sized> PartialEq for ByPtr<$type<T>> {
+            fn eq(&self, other: &Self) -> bool {
+                self.addr() == other.addr()
+            }
+        }
+
+        impl<T: ?Sized> Eq for ByPtr<$type<T>> {}
+
+        impl<T: ?Sized> PartialOrd for ByPtr<$type<T>> {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl<T: ?Sized> Ord for ByPtr<$type<T>> {
+            fn cmp(&self, other: &Self) -> Ordering {
+                self.addr().cmp(&other.addr())
+            }
+        }
+
+        impl<T: ?Sized> Hash for ByPtr<$type<T>> {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                self.addr().hash(state);
+            }
+        }
+    };
+}
+
+by_ptr!(RCell, RCell::addr);
+by_ptr!(
+    Strong,
+    |strong: &Strong<T>| Strong::as_ptr(strong) as *const () as usize
+);
+by_ptr!(Weak, |weak: &Weak<T>| Weak::as_ptr(weak) as *const ()
+    as usize);
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::ByPtr;
+    use crate::{RCell, Strong};
+
+    #[test]
+    fn identity() {
+        // f64 is neither Hash nor Eq
+        let strong = Strong::new(1.0f64);
+        let mut set = HashSet::new();
+        assert!(set.insert(ByPtr(RCell::from(strong.clone()))));
+        assert!(!set.insert(ByPtr(RCell::from(Strong::downgrade(&strong)))));
+        assert!(set.insert(ByPtr(RCell::new(1.0))));
+        assert!(set.insert(ByPtr(RCell::empty())));
+        assert!(!set.insert(ByPtr(RCell::empty())));
+        assert_eq!(set.len(), 3);
+
+        assert_eq!(ByPtr(strong.clone()), ByPtr(strong.clone()));
+        assert_ne!(ByPtr(strong), ByPtr(Strong::new(1.0)));
+    }
+}
@@ -0,0 +1,872 @@
+//! Keyed cache holding its values in RCells.
+
+use std::borrow::Borrow;
+use std::collections::{hash_map, HashMap};
+use std::fmt::Write;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::time::{Duration, Instant};
+
+use crate::iter::drain_map;
+use crate::{CostOf, Counts, DrainDead, RCell, Strong};
+
+/// Usage information of a cache entry, passed to a `CachePolicy`.
+#[derive(Debug, Clone, Copy)]
+pub struct Usage {
+    /// When the value was inserted.
+    pub inserted: Instant,
+    /// Logical time of the last access, increases with every access to the cache.
+    pub last_used: u64,
+    /// Number of accesses since insertion.
+    pub hits: u64,
+}
+
+/// Decides which entries of a `RCellCache` stay retained. Entries with the lowest score get
+/// released first when the capacity is exceeded.
+pub trait CachePolicy {
+    /// Returns the score of an entry.
+    fn score(&self, usage: &Usage) -> u64;
+
+    /// Called with the hash of the key on every lookup and insertion, hits and misses alike.
+    fn record(&mut self, _key_hash: u64) {}
+
+    /// Called when inserting a new key into a full cache with the hashes of the new key and
+    /// of the entry that would be released for it. Returning 'false' keeps the victim and
+    /// stores the new value released instead. Admits everything by default.
+    fn admit(&self, _candidate: u64, _victim: u64) -> bool {
+        true
+    }
+}
+
+/// Least recently used entries get released first.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Lru;
+
+impl CachePolicy for Lru {
+    fn score(&self, usage: &Usage) -> u64 {
+        usage.last_used
+    }
+}
+
+/// Least frequently used entries get released first.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Lfu;
+
+impl CachePolicy for Lfu {
+    fn score(&self, usage: &Usage) -> u64 {
+        usage.hits
+    }
+}
+
+impl<F: Fn(&Usage) -> u64> CachePolicy for F {
+    fn score(&self, usage: &Usage) -> u64 {
+        self(usage)
+    }
+}
+
+/// Diagnostic information on a cache entry, returned by `RCellCache::iter_info()`.
+#[derive(Debug)]
+pub struct EntryInfo<'a, K, V> {
+    /// The key.
+    pub key: &'a K,
+    /// The cell holding the value.
+    pub cell: &'a RCell<V>,
+    /// Usage information.
+    pub usage: Usage,
+}
+
+impl<K, V> EntryInfo<'_, K, V> {
+    /// Returns 'true' when the cache retains the value.
+    pub fn retained(&self) -> bool {
+        self.cell.retained()
+    }
+
+    /// Returns the reference counts of the value, a strong count of 0 means it got dropped.
+    pub fn counts(&self) -> Counts {
+        self.cell.refcounts()
+    }
+
+    /// Returns the time since the value was inserted.
+    pub fn age(&self) -> Duration {
+        self.usage.inserted.elapsed()
+    }
+
+    /// Returns the approximate cost of the value, 0 when it got dropped.
+    pub fn cost(&self) -> usize
+    where
+        V: CostOf,
+    {
+        self.cell.request().map_or(0, |value| value.cost_of())
+    }
+}
+
+/// An entry of a `RCellCache` as returned by `RCellCache::entry()`, mirroring the entry API
+/// of `HashMap`. Each operation looks the key up once.
+#[derive(Debug)]
+pub struct CacheEntry<'a, K, V, P, E = ()> {
+    cache: &'a mut RCellCache<K, V, P, E>,
+    key: K,
+}
+
+impl<K: Eq + Hash, V, P: CachePolicy, E> CacheEntry<'_, K, V, P, E> {
+    /// Returns the key of this entry.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    // for ShardedCacheEntry
+    #[cfg(feature = "sync")]
+    pub(crate) fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Returns the value and marks it as used, retaining it again when it was released but
+    /// is still alive, see `RCellCache::get()`.
+    pub fn or_retain(self) -> Option<Strong<V>> {
+        self.cache.get(&self.key)
+    }
+
+    /// Returns the value like `or_retain()`, calls `f` and inserts its result when there is
+    /// no live value.
+    pub fn or_insert_with(self, f: impl FnOnce() -> V) -> Strong<V> {
+        let cache = self.cache;
+        let hash = cache.touch(&self.key);
+        let clock = cache.clock;
+        let strong = match cache.entries.entry(self.key) {
+            hash_map::Entry::Occupied(mut occupied) => match occupied.get_mut().hit(clock) {
+                Some((strong, true)) => return strong,
+                Some((strong, false)) => {
+                    cache.retained += 1;
+                    cache.evict();
+                    return strong;
+                }
+                None => {
+                    cache.failures.remove(occupied.key());
+                    let strong = Strong::new(f());
+                    occupied.insert(Slot::new(strong.clone(), clock));
+                    strong
+                }
+            },
+            hash_map::Entry::Vacant(vacant) => {
+                cache.failures.remove(vacant.key());
+                let strong = Strong::new(f());
+                vacant.insert(Slot::new(strong.clone(), clock));
+                strong
+            }
+        };
+        cache.retained += 1;
+        cache.admit(hash);
+        strong
+    }
+
+    /// Replaces a live value with the one computed by `f` from it and marks it as used like
+    /// `or_retain()`. Does nothing when there is no live value.
+    pub fn and_modify(self, f: impl FnOnce(&V) -> V) -> Self {
+        let cache = &mut *self.cache;
+        cache.touch(&self.key);
+        if let Some(slot) = cache.entries.get_mut(&self.key) {
+            if let Some((_, was_retained)) = slot.hit(cache.clock) {
+                slot.cell.update(f);
+                if !was_retained {
+                    cache.retained += 1;
+                    cache.evict();
+                }
+            }
+        }
+        self
+    }
+}
+
+fn key_hash<Q: Hash + ?Sized>(key: &Q) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[derive(Debug)]
+struct Slot<V> {
+    cell: RCell<V>,
+    usage: Usage,
+}
+
+impl<V> Slot<V> {
+    fn new(strong: Strong<V>, clock: u64) -> Self {
+        Slot {
+            cell: RCell::from(strong),
+            usage: Usage {
+                inserted: Instant::now(),
+                last_used: clock,
+                hits: 0,
+            },
+        }
+    }
+
+    /// Retains the value and marks it as used, returns it and whether it was retained before.
+    fn hit(&mut self, clock: u64) -> Option<(Strong<V>, bool)> {
+        let was_retained = self.cell.retained();
+        let strong = self.cell.retain()?;
+        self.usage.last_used = clock;
+        self.usage.hits += 1;
+        Some((strong, was_retained))
+    }
+}
+
+/// A map from keys to values held in RCells. At most `capacity` values are retained, the
+/// others are only weakly referenced and stay available as long as something else keeps
+/// them alive. Which values stay retained is decided by the `CachePolicy`, least recently
+/// used by default.
+///
+/// Released entries whose value got dropped are removed on access or by `purge()`.
+///
+/// Loader failures of type `E` can be cached as well, see `set_negative_ttl()`.
+#[derive(Debug)]
+pub struct RCellCache<K, V, P = Lru, E = ()> {
+    capacity: usize,
+    policy: P,
+    clock: u64,
+    retained: usize,
+    entries: HashMap<K, Slot<V>>,
+    negative_ttl: Option<Duration>,
+    failures: HashMap<K, (Instant, E)>,
+}
+
+impl<K: Eq + Hash, V> RCellCache<K, V> {
+    /// Creates a LRU cache retaining at most `capacity` values.
+    pub fn new(capacity: usize) -> Self {
+        Self::with_policy(capacity, Lru)
+    }
+}
+
+impl<K: Eq + Hash, V, P: CachePolicy> RCellCache<K, V, P> {
+    /// Creates a cache retaining at most `capacity` values selected by `policy`.
+    pub fn with_policy(capacity: usize, policy: P) -> Self {
+        RCellCache {
+            capacity,
+            policy,
+            clock: 0,
+            retained: 0,
+            entries: HashMap::new(),
+            negative_ttl: None,
+            failures: HashMap::new(),
+        }
+    }
+}
+
+impl<K: Eq + Hash, V, P: CachePolicy, E> RCellCache<K, V, P, E> {
+    /// Changes the error type of the loaders passed to `try_get_or_insert_with()`. Cached
+    /// failures are forgotten.
+    pub fn with_error<F>(self) -> RCellCache<K, V, P, F> {
+        RCellCache {
+            capacity: self.capacity,
+            policy: self.policy,
+            clock: self.clock,
+            retained: self.retained,
+            entries: self.entries,
+            negative_ttl: self.negative_ttl,
+            failures: HashMap::new(),
+        }
+    }
+
+    /// Returns the number of entries, including released ones whose value may be gone.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns 'true' when there are no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the number of retained values.
+    pub fn retained(&self) -> usize {
+        self.retained
+    }
+
+    /// Returns the number of values which may be retained at once.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Changes the capacity, releases surplus entries.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    /// Returns the value for `key` and marks it as used. A released entry whose value is
+    /// still alive gets retained again.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<Strong<V>>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.touch(key);
+        match self.entries.get_mut(key)?.hit(self.clock) {
+            Some((strong, was_retained)) => {
+                if !was_retained {
+                    self.retained += 1;
+                    self.evict();
+                }
+                Some(strong)
+            }
+            None => {
+                self.entries.remove(key);
+                None
+            }
+        }
+    }
+
+    /// Returns the value for `key` without marking it as used or retaining it.
+    pub fn peek<Q>(&self, key: &Q) -> Option<Strong<V>>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.entries.get(key)?.cell.request()
+    }
+
+    /// Returns 'true' when there is an entry for `key` whose value is alive.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.peek(key).is_some()
+    }
+
+    /// Inserts a value, replacing any previous entry for `key`.
+    pub fn insert(&mut self, key: K, value: V) -> Strong<V> {
+        self.insert_strong(key, Strong::new(value))
+    }
+
+    /// Inserts an existing `Strong<V>`, replacing any previous entry for `key`.
+    pub fn insert_strong(&mut self, key: K, strong: Strong<V>) -> Strong<V> {
+        let hash = self.touch(&key);
+        self.failures.remove(&key);
+        if let Some(old) = self
+            .entries
+            .insert(key, Slot::new(strong.clone(), self.clock))
+        {
+            if old.cell.retained() {
+                self.retained -= 1;
+            }
+        }
+        self.retained += 1;
+        self.admit(hash);
+        strong
+    }
+
+    /// Returns the entry for `key` for insert or retain flows in a single call.
+    pub fn entry(&mut self, key: K) -> CacheEntry<'_, K, V, P, E> {
+        CacheEntry { cache: self, key }
+    }
+
+    /// Returns the value for `key`, calls `loader` and inserts its result when there is none.
+    pub fn get_or_insert_with(&mut self, key: K, loader: impl FnOnce() -> V) -> Strong<V> {
+        self.entry(key).or_insert_with(loader)
+    }
+
+    /// Returns how long loader failures are cached, `None` when they are not.
+    pub fn negative_ttl(&self) -> Option<Duration> {
+        self.negative_ttl
+    }
+
+    /// Enables caching of loader failures in `try_get_or_insert_with()` for `ttl`, usually
+    /// shorter than values are kept. `None` disables it and forgets the cached failures.
+    /// At most as many failures as the capacity are kept, expired ones are swept when a new
+    /// failure is cached and the one expiring first makes room when still full.
+    pub fn set_negative_ttl(&mut self, ttl: Option<Duration>) {
+        self.negative_ttl = ttl;
+        if ttl.is_none() {
+            self.failures.clear();
+        }
+    }
+
+    /// Returns the value for `key`, calls `loader` and inserts its result when there is none.
+    /// When negative caching is enabled a failure of the loader is remembered and returned
+    /// without calling the loader again until the negative ttl expired.
+    pub fn try_get_or_insert_with(
+        &mut self,
+        key: K,
+        loader: impl FnOnce() -> Result<V, E>,
+    ) -> Result<Strong<V>, E>
+    where
+        E: Clone,
+    {
+        if let Some(strong) = self.get(&key) {
+            return Ok(strong);
+        }
+        if let Some(error) = self.failure(&key) {
+            return Err(error);
+        }
+        match loader() {
+            Ok(value) => Ok(self.insert(key, value)),
+            Err(error) => {
+                self.fail(key, error.clone());
+                Err(error)
+            }
+        }
+    }
+
+    /// Returns the cached failure for `key` unless it expired.
+    pub(crate) fn failure(&mut self, key: &K) -> Option<E>
+    where
+        E: Clone,
+    {
+        let (expires, error) = self.failures.get(key)?;
+        if *expires > Instant::now() {
+            return Some(error.clone());
+        }
+        self.failures.remove(key);
+        None
+    }
+
+    /// Caches a failure of the loader for `key` when negative caching is enabled.
+    pub(crate) fn fail(&mut self, key: K, error: E) {
+        let Some(ttl) = self.negative_ttl else {
+            return;
+        };
+        let now = Instant::now();
+        self.failures.retain(|_, (expires, _)| *expires > now);
+        while self.failures.len() >= self.capacity.max(1) {
+            let Some(first) = self.failures.values().map(|(expires, _)| *expires).min() else {
+                break;
+            };
+            let mut removed = false;
+            self.failures.retain(|_, (expires, _)| {
+                let keep = removed || *expires != first;
+                removed |= !keep;
+                keep
+            });
+        }
+        self.failures.insert(key, (now + ttl, error));
+    }
+
+    /// Removes the entry and any cached failure for `key`, returns its value when alive.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<Strong<V>>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.failures.remove(key);
+        let entry = self.entries.remove(key)?;
+        if entry.cell.retained() {
+            self.retained -= 1;
+        }
+        entry.cell.request()
+    }
+
+    /// Removes all entries and cached failures.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.failures.clear();
+        self.retained = 0;
+    }
+
+    /// Removes the released entries whose value got dropped and the expired failures,
+    /// returns how many entries were removed.
+    pub fn purge(&mut self) -> usize {
+        let now = Instant::now();
+        self.failures.retain(|_, (expires, _)| *expires > now);
+        let before = self.entries.len();
+        self.entries.retain(|_, entry| entry.cell.refcount() > 0);
+        before - self.entries.len()
+    }
+
+    /// Retains the entries whose key and value match `f` and releases the others in one pass,
+    /// for policy sweeps. Entries whose value got dropped are removed. The capacity still
+    /// applies, surplus entries are released by the `CachePolicy`. Returns the number of
+    /// matching entries.
+    pub fn retain_where(&mut self, mut f: impl FnMut(&K, &V) -> bool) -> usize {
+        let mut matching = 0;
+        self.entries
+            .retain(|key, entry| match entry.cell.request() {
+                Some(value) => {
+                    if f(key, &value) {
+                        entry.cell.retain();
+                        matching += 1;
+                    } else {
+                        entry.cell.release();
+                    }
+                    true
+                }
+                None => false,
+            });
+        self.retained = matching;
+        self.evict();
+        matching
+    }
+
+    /// Retains and marks as used the entries for all `keys`, like calling `get()` on each.
+    /// Returns the number of entries found alive.
+    pub fn retain_keys<'a, Q>(&mut self, keys: impl IntoIterator<Item = &'a Q>) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized + 'a,
+    {
+        keys.into_iter()
+            .filter(|key| self.get(*key).is_some())
+            .count()
+    }
+
+    /// Releases all entries, values not used elsewhere get dropped.
+    pub fn release_all(&mut self) {
+        self.entries
+            .values_mut()
+            .for_each(|entry| entry.cell.release());
+        self.retained = 0;
+    }
+
+    /// Returns the keys of the retained entries in eviction order, the entry which would be
+    /// released first comes first. Together with `warm_up()` this lets a service persist
+    /// its working set and restart with a warm cache.
+    pub fn warm_keys(&self) -> Vec<K>
+    where
+        K: Clone,
+    {
+        self.warm_set()
+            .into_iter()
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    /// Like `warm_keys()` but returns the values as well, for persisting them with the keys.
+    pub fn warm_entries(&self) -> Vec<(K, Strong<V>)>
+    where
+        K: Clone,
+    {
+        self.warm_set()
+            .into_iter()
+            .filter_map(|(key, entry)| Some((key.clone(), entry.cell.request()?)))
+            .collect()
+    }
+
+    fn warm_set(&self) -> Vec<(&K, &Slot<V>)> {
+        let mut retained: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.cell.retained())
+            .collect();
+        retained.sort_by_key(|(_, entry)| self.policy.score(&entry.usage));
+        retained
+    }
+
+    /// Inserts the values for `keys` as returned by `warm_keys()`, in order. `loader`
+    /// produces the value for a key from the backing store or from persisted data, keys it
+    /// returns `None` for are skipped. Returns the number of inserted entries.
+    pub fn warm_up(
+        &mut self,
+        keys: impl IntoIterator<Item = K>,
+        mut loader: impl FnMut(&K) -> Option<V>,
+    ) -> usize {
+        let mut inserted = 0;
+        for key in keys {
+            if let Some(value) = loader(&key) {
+                self.insert(key, value);
+                inserted += 1;
+            }
+        }
+        inserted
+    }
+
+    /// Returns the approximate number of bytes held by the retained values, see `CostOf`.
+    pub fn retained_cost(&self) -> usize
+    where
+        V: CostOf,
+    {
+        self.entries
+            .values()
+            .filter(|entry| entry.cell.retained())
+            .filter_map(|entry| entry.cell.request())
+            .map(|value| value.cost_of())
+            .sum()
+    }
+
+    /// Iterates over the keys and cells of all entries.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &RCell<V>)> + '_ {
+        self.entries.iter().map(|(key, entry)| (key, &entry.cell))
+    }
+
+    /// Iterates over diagnostic information of all entries.
+    pub fn iter_info(&self) -> impl Iterator<Item = EntryInfo<'_, K, V>> + '_ {
+        self.entries.iter().map(|(key, entry)| EntryInfo {
+            key,
+            cell: &entry.cell,
+            usage: entry.usage,
+        })
+    }
+
+    /// Returns a human readable listing of all entries, one per line, for debugging consoles
+    /// and admin endpoints.
+    pub fn dump(&self) -> String
+    where
+        K: std::fmt::Debug,
+    {
+        let mut dump = String::new();
+        for info in self.iter_info() {
+            let _ = writeln!(
+                dump,
+                "{:?}: {:?} age={:?} hits={}",
+                info.key,
+                info.cell,
+                info.age(),
+                info.usage.hits
+            );
+        }
+        dump
+    }
+
+    /// Advances the clock and records an access to `key` with the policy, returns its hash.
+    fn touch<Q: Hash + ?Sized>(&mut self, key: &Q) -> u64 {
+        self.clock += 1;
+        let hash = key_hash(key);
+        self.policy.record(hash);
+        hash
+    }
+
+    /// Called after inserting a retained value for a key hashing to `hash` at the current
+    /// clock. When that exceeds the capacity the policy decides whether the new value is
+    /// admitted in place of the victim or is kept released.
+    fn admit(&mut self, hash: u64) {
+        if self.retained > self.capacity {
+            let clock = self.clock;
+            let policy = &self.policy;
+            let victim = self
+                .entries
+                .iter()
+                .filter(|(_, entry)| entry.cell.retained() && entry.usage.last_used != clock)
+                .min_by_key(|(_, entry)| policy.score(&entry.usage))
+                .map(|(key, _)| key_hash(key));
+            if victim.is_some_and(|victim| !policy.admit(hash, victim)) {
+                if let Some(new) = self
+                    .entries
+                    .values_mut()
+                    .find(|entry| entry.usage.last_used == clock)
+                {
+                    new.cell.release();
+                    self.retained -= 1;
+                }
+            }
+        }
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        if self.retained <= self.capacity {
+            return;
+        }
+        let mut retained: Vec<_> = self
+            .entries
+            .values_mut()
+            .filter(|entry| entry.cell.retained())
+            .collect();
+        let policy = &self.policy;
+        retained.sort_by_key(|entry| policy.score(&entry.usage));
+        let surplus = self.retained - self.capacity;
+        for entry in retained.into_iter().take(surplus) {
+            entry.cell.release();
+        }
+        self.retained = self.capacity;
+    }
+}
+
+impl<K: Eq + Hash, V, P: CachePolicy, E> DrainDead for RCellCache<K, V, P, E> {
+    type Item = (K, RCell<V>);
+
+    /// Removes the released entries whose value got dropped, see also `purge()`.
+    fn drain_dead(&mut self) -> Vec<(K, RCell<V>)> {
+        drain_map(&mut self.entries, |entry| entry.cell.refcount() == 0)
+            .into_iter()
+            .map(|(key, entry)| (key, entry.cell))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Lfu, Lru, RCellCache};
+
+    #[test]
+    fn lru() {
+        let mut cache = RCellCache::new(2);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        assert_eq!(*cache.get("a").unwrap(), 1);
+        let c = cache.insert("c", 3);
+        // 'b' was least recently used and got dropped
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.retained(), 2);
+
+        // a released value which is still alive can be found and gets retained again
+        cache.set_capacity(0);
+        assert_eq!(cache.retained(), 0);
+        assert_eq!(cache.peek("a"), None);
+        cache.set_capacity(1);
+        assert_eq!(cache.get("c"), Some(c));
+        assert_eq!(cache.retained(), 1);
+        assert_eq!(cache.retained_cost(), 4);
+        assert_eq!(cache.purge(), 1);
+    }
+
+    #[test]
+    fn info() {
+        let mut cache = RCellCache::new(1);
+        let a = cache.insert("a", String::from("foo"));
+        cache.get("a");
+        cache.insert("b", String::from("bar"));
+        let mut info: Vec<_> = cache.iter_info().collect();
+        info.sort_by_key(|info| info.key);
+        assert!(!info[0].retained());
+        assert_eq!(info[0].counts().strong, 1);
+        assert_eq!(info[0].usage.hits, 1);
+        assert!(info[1].retained());
+        assert_eq!(info[1].cost(), std::mem::size_of::<String>() + 3);
+        assert!(info[1].age() < std::time::Duration::from_secs(60));
+        drop(a);
+
+        let dump = cache.dump();
+        assert_eq!(dump.lines().count(), 2);
+        assert!(dump.contains("\"b\": RCell { state: Strong, strong: 1, weak: 0 } age="));
+    }
+
+    #[test]
+    fn drain_dead() {
+        use crate::DrainDead;
+
+        let mut cache = RCellCache::new(1);
+        let a = cache.insert("a", 1);
+        cache.insert("b", 2);
+        cache.insert("c", 3);
+        let dead: Vec<_> = cache.drain_dead().into_iter().map(|(key, _)| key).collect();
+        assert_eq!(dead, vec!["b"]);
+        assert_eq!(cache.peek("a"), Some(a));
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn lfu() {
+        let mut cache = RCellCache::with_policy(1, Lfu);
+        let a = cache.insert("a", 1);
+        cache.get("a");
+        cache.insert("b", 2);
+        assert_eq!(cache.peek("b"), None);
+        assert_eq!(cache.peek("a"), Some(a));
+    }
+
+    #[test]
+    fn get_or_insert_with() {
+        let mut cache = RCellCache::new(4);
+        let mut loads = 0;
+        for _ in 0..3 {
+            let value = cache.get_or_insert_with(String::from("key"), || {
+                loads += 1;
+                42
+            });
+            assert_eq!(*value, 42);
+        }
+        assert_eq!(loads, 1);
+        assert_eq!(*cache.remove("key").unwrap(), 42);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn bulk() {
+        let mut cache = RCellCache::new(4);
+        let a = cache.insert("a", 1);
+        cache.insert("b", 2);
+        cache.insert("c", 3);
+        assert_eq!(cache.retain_where(|_, value| value % 2 == 1), 2);
+        assert_eq!(cache.retained(), 2);
+        // b got dropped on release and is gone in the next sweep
+        assert_eq!(cache.retain_where(|_, _| true), 2);
+        assert_eq!(cache.len(), 2);
+
+        cache.release_all();
+        assert_eq!(cache.retained(), 0);
+        assert_eq!(cache.retain_keys(["a", "c", "d"]), 1);
+        assert_eq!(cache.retained(), 1);
+        assert!(cache
+            .iter()
+            .all(|(key, cell)| cell.retained() == (*key == "a")));
+        drop(a);
+    }
+
+    #[test]
+    fn warm() {
+        let mut cache = RCellCache::new(2);
+        let a = cache.insert("a", 1);
+        cache.insert("b", 2);
+        cache.insert("c", 3);
+        cache.get("b");
+        assert_eq!(cache.warm_keys(), ["c", "b"]);
+        let entries = cache.warm_entries();
+        assert_eq!(*entries[1].1, 2);
+
+        let mut warm = RCellCache::new(2);
+        assert_eq!(
+            warm.warm_up(cache.warm_keys(), |key| (*key != "c").then_some(20)),
+            1
+        );
+        assert_eq!(warm.warm_up(["a", "c"], |_| Some(10)), 2);
+        assert_eq!(warm.warm_keys(), ["a", "c"]);
+        drop(a);
+    }
+
+    #[test]
+    fn negative() {
+        let mut cache = RCellCache::new(2).with_error::<&str>();
+        let loads = std::cell::Cell::new(0);
+        let load = |cache: &mut RCellCache<&'static str, u32, Lru, &'static str>, key| {
+            cache.try_get_or_insert_with(key, || {
+                loads.set(loads.get() + 1);
+                Err("not found")
+            })
+        };
+        assert_eq!(load(&mut cache, "missing"), Err("not found"));
+        assert_eq!(load(&mut cache, "missing"), Err("not found"));
+
+        cache.set_negative_ttl(Some(Duration::from_secs(60)));
+        assert_eq!(load(&mut cache, "missing"), Err("not found"));
+        assert_eq!(load(&mut cache, "missing"), Err("not found"));
+
+        // inserting a value replaces the failure
+        cache.insert("missing", 1);
+        assert_eq!(*load(&mut cache, "missing").unwrap(), 1);
+        assert_eq!(loads.get(), 3);
+        assert_eq!(
+            cache.try_get_or_insert_with("other", || Ok(2)),
+            Ok(cache.peek("other").unwrap())
+        );
+
+        // bounded by the capacity, the failure expiring first makes room
+        for key in ["x", "y", "z"] {
+            assert_eq!(load(&mut cache, key), Err("not found"));
+        }
+        assert_eq!(cache.failures.len(), 2);
+        assert!(!cache.failures.contains_key("x"));
+        assert_eq!(load(&mut cache, "z"), Err("not found"));
+        assert_eq!(loads.get(), 6);
+
+        // expired failures are swept when caching a new one
+        cache.set_negative_ttl(Some(Duration::ZERO));
+        assert_eq!(load(&mut cache, "x"), Err("not found"));
+        cache.set_negative_ttl(Some(Duration::from_secs(60)));
+        assert_eq!(load(&mut cache, "w"), Err("not found"));
+        assert!(!cache.failures.contains_key("x"));
+    }
+
+    #[test]
+    fn entry() {
+        let mut cache = RCellCache::new(2);
+        assert_eq!(cache.entry("a").or_retain(), None);
+        assert_eq!(*cache.entry("a").or_insert_with(|| 1), 1);
+        assert_eq!(*cache.entry("a").or_insert_with(|| 2), 1);
+        let a = cache.entry("a").and_modify(|value| value + 10).or_retain();
+        assert_eq!(a.as_deref(), Some(&11));
+        assert_eq!(*cache.entry("b").and_modify(|_| 0).or_insert_with(|| 3), 3);
+        assert_eq!(cache.entry("b").key(), &"b");
+        assert_eq!(cache.retained(), 2);
+    }
+}
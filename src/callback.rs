@@ -0,0 +1,144 @@
+//! Callbacks which don't keep their receiver alive.
+
+use crate::{RCell, Strong};
+
+/// Closures and receivers of a `WeakCallback`, any `Send + Sync` type with the **sync**
+/// feature so that callbacks can be shared between threads, any type otherwise.
+#[cfg(feature = "sync")]
+pub trait CallbackTarget: Send + Sync {}
+#[cfg(feature = "sync")]
+impl<T: ?Sized + Send + Sync> CallbackTarget for T {}
+
+/// Closures and receivers of a `WeakCallback`, any `Send + Sync` type with the **sync**
+/// feature so that callbacks can be shared between threads, any type otherwise.
+#[cfg(not(feature = "sync"))]
+pub trait CallbackTarget {}
+#[cfg(not(feature = "sync"))]
+impl<T: ?Sized> CallbackTarget for T {}
+
+trait Target<Args>: CallbackTarget {
+    fn call(&self, args: Args) -> bool;
+    fn alive(&self) -> bool;
+}
+
+struct Receiver<R: ?Sized, Args> {
+    cell: RCell<R>,
+    method: fn(&R, Args),
+}
+
+impl<R: ?Sized + CallbackTarget, Args> Target<Args> for Receiver<R, Args> {
+    fn call(&self, args: Args) -> bool {
+        match self.cell.request() {
+            Some(receiver) => {
+                (self.method)(&receiver, args);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn alive(&self) -> bool {
+        self.cell.refcount() > 0
+    }
+}
+
+/// A callback holding only a weak reference to its closure or receiver. Subscribing with
+/// a `WeakCallback` does not keep the subscriber alive, calling it after the subscriber got
+/// dropped does nothing.
+pub struct WeakCallback<Args> {
+    target: Box<dyn Target<Args>>,
+}
+
+impl<Args: 'static> WeakCallback<Args> {
+    /// Creates a callback calling the closure `f`.
+    pub fn new<F: Fn(Args) + CallbackTarget + 'static>(f: &Strong<F>) -> Self {
+        Self::from_receiver(f, |f, args| f(args))
+    }
+
+    /// Creates a callback calling `method` on `receiver`.
+    pub fn from_receiver<R: ?Sized + CallbackTarget + 'static>(
+        receiver: &Strong<R>,
+        method: fn(&R, Args),
+    ) -> Self {
+        WeakCallback {
+            target: Box::new(Receiver {
+                cell: RCell::from(Strong::downgrade(receiver)),
+                method,
+            }),
+        }
+    }
+}
+
+impl<Args> WeakCallback<Args> {
+    /// Invokes the callback when its target is still alive. Returns 'false' when it got
+    /// dropped.
+    pub fn call(&self, args: Args) -> bool {
+        self.target.call(args)
+    }
+
+    /// Returns 'true' when the target is still alive.
+    pub fn is_alive(&self) -> bool {
+        self.target.alive()
+    }
+}
+
+impl<Args> std::fmt::Debug for WeakCallback<Args> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WeakCallback")
+            .field("alive", &self.is_alive())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::WeakCallback;
+    use crate::Strong;
+
+    #[test]
+    fn closure() {
+        let sum = Strong::new(AtomicU32::new(0));
+        let add = {
+            let sum = sum.clone();
+            Strong::new(move |n: u32| {
+                sum.fetch_add(n, Ordering::SeqCst);
+            })
+        };
+        let callback = WeakCallback::new(&add);
+        assert!(callback.call(2));
+        assert!(callback.call(3));
+        drop(add);
+        assert!(!callback.is_alive());
+        assert!(!callback.call(4));
+        assert_eq!(sum.load(Ordering::SeqCst), 5);
+    }
+
+    #[test]
+    fn receiver() {
+        let counter = Strong::new(AtomicU32::new(0));
+        let callback = WeakCallback::from_receiver(&counter, |counter, n| {
+            counter.fetch_add(n, Ordering::SeqCst);
+        });
+        assert!(callback.call(1));
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+        drop(counter);
+        assert!(!callback.call(1));
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn send_sync() {
+        fn shared<T: Send + Sync>(_: &T) {}
+        let counter = Strong::new(AtomicU32::new(0));
+        let callback = WeakCallback::from_receiver(&counter, |counter, n| {
+            counter.fetch_add(n, Ordering::SeqCst);
+        });
+        shared(&callback);
+        std::thread::scope(|scope| {
+            scope.spawn(|| assert!(callback.call(1)));
+        });
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,130 @@
+//! Canonical instances per key.
+
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::{AnyValue, DynAny, RCell, Strong, Weak};
+
+type Table = HashMap<(TypeId, TypeId), Box<DynAny>>;
+
+/// The weak instances of one key and value type.
+struct Instances<K, T> {
+    entries: HashMap<K, Weak<T>>,
+    purge_at: usize,
+}
+
+#[cfg(feature = "sync")]
+fn with_table<R>(f: impl FnOnce(&mut Table) -> R) -> R {
+    static TABLE: std::sync::OnceLock<std::sync::Mutex<Table>> = std::sync::OnceLock::new();
+    let mut table = TABLE
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|poison| poison.into_inner());
+    f(&mut table)
+}
+
+#[cfg(not(feature = "sync"))]
+fn with_table<R>(f: impl FnOnce(&mut Table) -> R) -> R {
+    thread_local! {
+        static TABLE: std::cell::RefCell<Table> = Default::default();
+    }
+    TABLE.with(|table| f(&mut table.borrow_mut()))
+}
+
+fn with_instances<K: AnyValue + Eq + Hash, T: AnyValue, R>(
+    f: impl FnOnce(&mut Instances<K, T>) -> R,
+) -> R {
+    with_table(|table| {
+        let instances = table
+            .entry((TypeId::of::<K>(), TypeId::of::<T>()))
+            .or_insert_with(|| {
+                Box::new(Instances::<K, T> {
+                    entries: HashMap::new(),
+                    purge_at: 16,
+                })
+            })
+            .downcast_mut::<Instances<K, T>>()
+            .expect("canonical table keyed by type");
+        f(instances)
+    })
+}
+
+impl<T: AnyValue> RCell<T> {
+    /// Returns the canonical instance for `key`, creating it with `f` when there is no live
+    /// one. At most one instance per key and type is alive at any time, only weak references
+    /// are kept, the instance is dropped when the last strong reference goes away. With the
+    /// **sync** feature the instances are shared process wide, without it per thread.
+    ///
+    /// `f` is called without holding any lock and may create other canonical instances. When
+    /// two threads race on the same key, the instance created first wins and is returned to
+    /// both.
+    pub fn canonical<K: AnyValue + Eq + Hash>(key: K, f: impl FnOnce() -> T) -> Strong<T> {
+        let lookup = |instances: &mut Instances<K, T>, key: &K| {
+            instances.entries.get(key).and_then(Weak::upgrade)
+        };
+        if let Some(strong) = with_instances(|instances| lookup(instances, &key)) {
+            return strong;
+        }
+        let new = Strong::new(f());
+        let (strong, lost) = with_instances(|instances| {
+            if let Some(strong) = lookup(instances, &key) {
+                return (strong, Some(new));
+            }
+            instances.entries.insert(key, Strong::downgrade(&new));
+            if instances.entries.len() >= instances.purge_at {
+                instances.entries.retain(|_, weak| weak.strong_count() > 0);
+                instances.purge_at = (instances.entries.len() * 2).max(16);
+            }
+            (new, None)
+        });
+        // the losing value is dropped without holding the lock, its destructor may use
+        // canonical instances as well
+        drop(lost);
+        strong
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{RCell, Strong};
+
+    #[test]
+    fn canonical() {
+        let a = RCell::canonical("schema.json", || String::from("a"));
+        let b = RCell::canonical("schema.json", || String::from("b"));
+        assert!(Strong::ptr_eq(&a, &b));
+        // same key, other type
+        assert_eq!(*RCell::canonical("schema.json", || 1u32), 1);
+        drop((a, b));
+        assert_eq!(*RCell::canonical("schema.json", || String::from("c")), "c");
+    }
+
+    #[test]
+    fn nested() {
+        let outer = RCell::canonical(1u8, || {
+            let inner = RCell::canonical(2u8, || 2u64);
+            *inner + 1
+        });
+        assert_eq!(*outer, 3);
+    }
+
+    #[test]
+    fn lost_race() {
+        struct Reentrant(u32);
+        impl Drop for Reentrant {
+            fn drop(&mut self) {
+                RCell::canonical(0u16, || 0u8);
+            }
+        }
+
+        // the inner call wins, the outer value is dropped and looks up another instance
+        let mut winner = None;
+        let canonical = RCell::canonical("lost_race", || {
+            winner = Some(RCell::canonical("lost_race", || Reentrant(1)));
+            Reentrant(2)
+        });
+        assert_eq!(canonical.0, 1);
+        assert!(Strong::ptr_eq(&canonical, &winner.unwrap()));
+    }
+}
@@ -0,0 +1,69 @@
+//! Values knowing their own cell.
+
+use crate::{RCell, State, Strong, Weak};
+
+/// Types which hold a weak RCell to themselves and can hand out references to themselves.
+/// Such values are created with `new_celled()`, which wires the cell up before the value
+/// exists.
+pub trait RCelled: Sized {
+    /// Returns the cell referencing this value.
+    fn cell(&self) -> &RCell<Self>;
+
+    /// Creates a value by passing a weak cell to itself to `f`.
+    fn new_celled(f: impl FnOnce(RCell<Self>) -> Self) -> Strong<Self> {
+        Strong::new_cyclic(|weak| f(RCell::from(weak.clone())))
+    }
+
+    /// Returns a strong reference to this value.
+    fn strong_self(&self) -> Option<Strong<Self>> {
+        self.cell().request()
+    }
+
+    /// Returns a weak reference to this value.
+    fn weak_self(&self) -> Weak<Self> {
+        match self.cell().state() {
+            State::Strong(strong) => Strong::downgrade(strong),
+            State::Weak(weak) => weak.clone(),
+            State::Empty => Weak::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RCelled;
+    use crate::{RCell, Strong};
+
+    struct Node {
+        this: RCell<Node>,
+        value: u32,
+    }
+
+    impl RCelled for Node {
+        fn cell(&self) -> &RCell<Node> {
+            &self.this
+        }
+    }
+
+    #[test]
+    fn celled() {
+        let node = Node::new_celled(|this| Node { this, value: 1 });
+        assert!(!node.cell().retained());
+        assert!(Strong::ptr_eq(&node.strong_self().unwrap(), &node));
+        let weak = node.weak_self();
+        assert_eq!(weak.upgrade().unwrap().value, 1);
+        drop(node);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn new_cyclic() {
+        let cell = RCell::new_cyclic(|weak| Node {
+            this: RCell::from(weak.clone()),
+            value: 2,
+        });
+        let strong = cell.request().unwrap();
+        assert!(Strong::ptr_eq(&strong.strong_self().unwrap(), &strong));
+        assert_eq!(strong.value, 2);
+    }
+}
@@ -0,0 +1,109 @@
+//! Approximate memory cost of values.
+
+use std::mem::size_of;
+
+/// Approximate number of bytes a value keeps alive, including its heap allocations. Used by
+/// the caches and retainers to report how much memory their retained entries hold.
+pub trait CostOf {
+    /// Returns the approximate cost in bytes.
+    fn cost_of(&self) -> usize;
+}
+
+macro_rules! cost_of_size {
+    ($($ty:ty),*) => {
+        $(
+            impl CostOf for $ty {
+                fn cost_of(&self) -> usize {
+                    size_of::<$ty>()
+                }
+            }
+        )*
+    };
+}
+
+cost_of_size!(
+    (),
+    bool,
+    char,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    f32,
+    f64
+);
+
+impl CostOf for str {
+    fn cost_of(&self) -> usize {
+        self.len()
+    }
+}
+
+impl CostOf for String {
+    fn cost_of(&self) -> usize {
+        size_of::<String>() + self.capacity()
+    }
+}
+
+impl<T: CostOf> CostOf for [T] {
+    fn cost_of(&self) -> usize {
+        self.iter().map(CostOf::cost_of).sum()
+    }
+}
+
+impl<T: CostOf, const N: usize> CostOf for [T; N] {
+    fn cost_of(&self) -> usize {
+        self.as_slice().cost_of()
+    }
+}
+
+impl<T: CostOf> CostOf for Vec<T> {
+    /// The elements plus the unused capacity.
+    fn cost_of(&self) -> usize {
+        size_of::<Vec<T>>()
+            + (self.capacity() - self.len()) * size_of::<T>()
+            + self.as_slice().cost_of()
+    }
+}
+
+impl<T: CostOf + ?Sized> CostOf for Box<T> {
+    fn cost_of(&self) -> usize {
+        size_of::<Box<T>>() + (**self).cost_of()
+    }
+}
+
+impl<T: CostOf> CostOf for Option<T> {
+    fn cost_of(&self) -> usize {
+        match self {
+            Some(value) => size_of::<Option<T>>() - size_of::<T>() + value.cost_of(),
+            None => size_of::<Option<T>>(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CostOf;
+
+    #[test]
+    fn costs() {
+        assert_eq!(1u32.cost_of(), 4);
+        assert_eq!("foo".cost_of(), 3);
+        let mut vec = Vec::with_capacity(4);
+        vec.push(1u16);
+        assert_eq!(vec.cost_of(), std::mem::size_of::<Vec<u16>>() + 8);
+        assert_eq!(Some(1u8).cost_of(), 2);
+        assert_eq!(
+            Box::new([1u8; 3]).cost_of(),
+            std::mem::size_of::<Box<[u8; 3]>>() + 3
+        );
+    }
+}
@@ -0,0 +1,114 @@
+//! Cell with nested retains.
+
+use std::ops::Deref;
+
+use crate::{RCell, Strong};
+
+/// A RCell counting its retains. Every successful `retain()` increments the counter and
+/// `release()` only downgrades the cell when the counter drops to zero. Independent
+/// subsystems can pin and unpin the same cell without coordinating with each other.
+/// Derefs to the inner `RCell<T>` for read only access.
+#[derive(Debug, Default)]
+pub struct CountedRCell<T: ?Sized> {
+    cell: RCell<T>,
+    retain_count: usize,
+}
+
+impl<T> CountedRCell<T> {
+    /// Creates a new CountedRCell retained once.
+    pub fn new(value: T) -> Self {
+        Self::from_rcell(RCell::new(value))
+    }
+}
+
+impl<T: ?Sized> CountedRCell<T> {
+    /// Wraps an existing RCell, a retained cell starts with a count of one.
+    pub fn from_rcell(cell: RCell<T>) -> Self {
+        let retain_count = cell.retained() as usize;
+        CountedRCell { cell, retain_count }
+    }
+
+    /// Returns how often the cell is retained.
+    pub fn retain_count(&self) -> usize {
+        self.retain_count
+    }
+
+    /// Retains the cell and increments the counter when the value is still alive.
+    pub fn retain(&mut self) -> Option<Strong<T>> {
+        let strong = self.cell.retain()?;
+        self.retain_count += 1;
+        Some(strong)
+    }
+
+    /// Decrements the counter, the cell is released when it drops to zero. Returns 'true'
+    /// when the cell got released.
+    pub fn release(&mut self) -> bool {
+        match self.retain_count {
+            0 => false,
+            1 => {
+                self.retain_count = 0;
+                self.cell.release();
+                true
+            }
+            _ => {
+                self.retain_count -= 1;
+                false
+            }
+        }
+    }
+
+    /// Removes the reference from the cell regardless of the counter.
+    pub fn remove(&mut self) {
+        self.retain_count = 0;
+        self.cell.remove();
+    }
+
+    /// Unwraps the inner RCell.
+    pub fn into_inner(self) -> RCell<T> {
+        self.cell
+    }
+}
+
+impl<T: ?Sized> Deref for CountedRCell<T> {
+    type Target = RCell<T>;
+
+    fn deref(&self) -> &RCell<T> {
+        &self.cell
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CountedRCell;
+    use crate::RCell;
+
+    #[test]
+    fn nested() {
+        let mut cell = CountedRCell::new(1);
+        let strong = cell.request().unwrap();
+        assert_eq!(cell.retain_count(), 1);
+        assert!(cell.retain().is_some());
+        assert_eq!(cell.retain_count(), 2);
+
+        assert!(!cell.release());
+        assert!(cell.retained());
+        assert!(cell.release());
+        assert!(!cell.retained());
+        assert!(!cell.release());
+
+        drop(strong);
+        assert_eq!(cell.retain(), None);
+        assert_eq!(cell.retain_count(), 0);
+    }
+
+    #[test]
+    fn from_weak() {
+        let strong = crate::Strong::new(1);
+        let mut cell = CountedRCell::from_rcell(RCell::from(crate::Strong::downgrade(&strong)));
+        assert_eq!(cell.retain_count(), 0);
+        cell.retain();
+        cell.remove();
+        assert_eq!(cell.retain_count(), 0);
+        assert_eq!(cell.request(), None);
+    }
+}
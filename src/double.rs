@@ -0,0 +1,89 @@
+//! Double buffered cell.
+
+use crate::{RCell, Replace, Strong};
+
+/// Holds a strong 'current' and a weak 'previous' value. `publish()` makes a new value
+/// current and demotes the old one to previous. Readers which still use the old value keep it
+/// alive, new readers get the fresh one.
+#[derive(Debug, Default)]
+pub struct DoubleRCell<T: ?Sized> {
+    current: RCell<T>,
+    previous: RCell<T>,
+}
+
+impl<T> DoubleRCell<T> {
+    /// Creates a DoubleRCell with `value` as current and no previous value.
+    pub fn new(value: T) -> Self {
+        Self::from_strong(Strong::new(value))
+    }
+
+    /// Makes `value` the current value, see `publish_strong()`.
+    pub fn publish(&mut self, value: T) -> Strong<T> {
+        self.publish_strong(Strong::new(value))
+    }
+}
+
+impl<T: ?Sized> DoubleRCell<T> {
+    /// Creates a DoubleRCell with `strong` as current and no previous value.
+    pub fn from_strong(strong: Strong<T>) -> Self {
+        DoubleRCell {
+            current: RCell::from(strong),
+            previous: RCell::empty(),
+        }
+    }
+
+    /// Makes `strong` the current value. The old current value becomes the previous value
+    /// which is only weakly referenced from now on. Returns the new current value.
+    pub fn publish_strong(&mut self, strong: Strong<T>) -> Strong<T> {
+        match self.current.request() {
+            Some(old) => self.previous.replace(Strong::downgrade(&old)),
+            None => self.previous.remove(),
+        }
+        self.current.replace(strong.clone());
+        strong
+    }
+
+    /// Returns the current value.
+    pub fn current(&self) -> Option<Strong<T>> {
+        self.current.request()
+    }
+
+    /// Returns the previous value when something still keeps it alive.
+    pub fn previous(&self) -> Option<Strong<T>> {
+        self.previous.request()
+    }
+
+    /// Returns the cell holding the current value.
+    pub fn current_cell(&self) -> &RCell<T> {
+        &self.current
+    }
+
+    /// Returns the cell holding the previous value.
+    pub fn previous_cell(&self) -> &RCell<T> {
+        &self.previous
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DoubleRCell;
+
+    #[test]
+    fn publish() {
+        let mut cell = DoubleRCell::new(1);
+        let reader = cell.current().unwrap();
+        cell.publish(2);
+        assert_eq!(*cell.current().unwrap(), 2);
+        assert_eq!(cell.previous(), Some(reader.clone()));
+        drop(reader);
+        assert_eq!(cell.previous(), None);
+
+        let reader = cell.current().unwrap();
+        assert_eq!(*cell.publish(3), 3);
+        assert_eq!(*cell.previous().unwrap(), 2);
+        // nothing kept 3 alive
+        cell.publish(4);
+        assert_eq!(cell.previous(), None);
+        assert_eq!(*reader, 2);
+    }
+}
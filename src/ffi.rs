@@ -0,0 +1,182 @@
+//! C FFI layer exporting the RCell lifecycle through opaque handles.
+//!
+//! Values are passed in as `void*` together with an optional destructor which gets called
+//! when the last strong reference to the value goes away. Cells (`rcell_t*`) and strong
+//! references obtained by `rcell_request()` (`rcell_strong_t*`) are opaque handles which
+//! must be freed with `rcell_free()` and `rcell_strong_free()` respectively.
+//!
+//! A matching C header is provided in `include/rcell.h`.
+
+use std::ffi::c_void;
+use std::ptr;
+
+use crate::{RCell, Strong};
+
+/// Destructor for a foreign value, called once when the value gets dropped.
+pub type RCellDestructor = Option<unsafe extern "C" fn(*mut c_void)>;
+
+/// A foreign value, a pointer and the destructor to release it.
+#[derive(Debug)]
+pub struct ForeignValue {
+    ptr: *mut c_void,
+    destructor: RCellDestructor,
+}
+
+// The host is responsible for the thread safety of the values it hands over.
+unsafe impl Send for ForeignValue {}
+unsafe impl Sync for ForeignValue {}
+
+impl Drop for ForeignValue {
+    fn drop(&mut self) {
+        if let Some(destructor) = self.destructor {
+            unsafe { destructor(self.ptr) }
+        }
+    }
+}
+
+/// Opaque cell handle, `rcell_t` on the C side.
+#[derive(Debug)]
+pub struct RCellHandle(RCell<ForeignValue>);
+
+/// Opaque strong reference, `rcell_strong_t` on the C side.
+#[derive(Debug)]
+pub struct RCellStrong(Strong<ForeignValue>);
+
+/// Creates a new strong cell holding `ptr`. The `destructor` (may be NULL) is called with
+/// `ptr` when the last strong reference gets dropped.
+///
+/// # Safety
+///
+/// `destructor` must be safe to call once with `ptr`, from any thread that drops the last
+/// strong reference.
+#[no_mangle]
+pub unsafe extern "C" fn rcell_new(
+    ptr: *mut c_void,
+    destructor: RCellDestructor,
+) -> *mut RCellHandle {
+    Box::into_raw(Box::new(RCellHandle(RCell::new(ForeignValue {
+        ptr,
+        destructor,
+    }))))
+}
+
+/// Tries to upgrade the cell to a strong reference. Returns 'true' on success.
+///
+/// # Safety
+///
+/// `cell` must be a valid handle obtained from `rcell_new()` or NULL.
+#[no_mangle]
+pub unsafe extern "C" fn rcell_retain(cell: *mut RCellHandle) -> bool {
+    match cell.as_mut() {
+        Some(cell) => cell.0.retain().is_some(),
+        None => false,
+    }
+}
+
+/// Downgrades the cell to a weak reference, see `RCell::release()`.
+///
+/// # Safety
+///
+/// `cell` must be a valid handle obtained from `rcell_new()` or NULL.
+#[no_mangle]
+pub unsafe extern "C" fn rcell_release(cell: *mut RCellHandle) {
+    if let Some(cell) = cell.as_mut() {
+        cell.0.release();
+    }
+}
+
+/// Tries to get a strong reference to the value. Returns NULL when the cell is empty or the
+/// value got dropped. The returned handle must be freed with `rcell_strong_free()`.
+///
+/// # Safety
+///
+/// `cell` must be a valid handle obtained from `rcell_new()` or NULL.
+#[no_mangle]
+pub unsafe extern "C" fn rcell_request(cell: *const RCellHandle) -> *mut RCellStrong {
+    match cell.as_ref().and_then(|cell| cell.0.request()) {
+        Some(strong) => Box::into_raw(Box::new(RCellStrong(strong))),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Returns the value pointer of a strong reference.
+///
+/// # Safety
+///
+/// `strong` must be a valid handle obtained from `rcell_request()` or NULL.
+#[no_mangle]
+pub unsafe extern "C" fn rcell_strong_get(strong: *const RCellStrong) -> *mut c_void {
+    match strong.as_ref() {
+        Some(strong) => strong.0.ptr,
+        None => ptr::null_mut(),
+    }
+}
+
+/// Frees a strong reference obtained from `rcell_request()`.
+///
+/// # Safety
+///
+/// `strong` must be a valid handle obtained from `rcell_request()` or NULL and must not be
+/// used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn rcell_strong_free(strong: *mut RCellStrong) {
+    if !strong.is_null() {
+        drop(Box::from_raw(strong));
+    }
+}
+
+/// Frees a cell handle.
+///
+/// # Safety
+///
+/// `cell` must be a valid handle obtained from `rcell_new()` or NULL and must not be used
+/// afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn rcell_free(cell: *mut RCellHandle) {
+    if !cell.is_null() {
+        drop(Box::from_raw(cell));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe extern "C" fn count_drop(_: *mut c_void) {
+        DROPPED.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn lifecycle() {
+        let mut value = 42u32;
+        let ptr = &mut value as *mut u32 as *mut c_void;
+        unsafe {
+            let cell = rcell_new(ptr, Some(count_drop));
+            let strong = rcell_request(cell);
+            assert_eq!(rcell_strong_get(strong), ptr);
+            rcell_release(cell);
+            assert!(rcell_retain(cell));
+            rcell_release(cell);
+            rcell_strong_free(strong);
+            assert_eq!(DROPPED.load(Ordering::SeqCst), 1);
+            assert!(rcell_request(cell).is_null());
+            assert!(!rcell_retain(cell));
+            rcell_free(cell);
+        }
+    }
+
+    #[test]
+    fn null_handles() {
+        unsafe {
+            assert!(!rcell_retain(ptr::null_mut()));
+            rcell_release(ptr::null_mut());
+            assert!(rcell_request(ptr::null()).is_null());
+            assert!(rcell_strong_get(ptr::null()).is_null());
+            rcell_strong_free(ptr::null_mut());
+            rcell_free(ptr::null_mut());
+        }
+    }
+}
@@ -0,0 +1,297 @@
+//! Cells with dependencies on each other.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+
+use crate::{RCell, Strong};
+
+/// Error returned when adding a dependency to a `CellGraph` fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphError {
+    /// One of the keys is not in the graph.
+    UnknownKey,
+    /// The dependency would create a cycle.
+    Cycle,
+}
+
+impl std::fmt::Display for GraphError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GraphError::UnknownKey => write!(f, "unknown key"),
+            GraphError::Cycle => write!(f, "dependency cycle"),
+        }
+    }
+}
+
+impl std::error::Error for GraphError {}
+
+#[derive(Debug)]
+struct Node<K, T> {
+    cell: RCell<T>,
+    upstream: HashSet<K>,
+    dependents: HashSet<K>,
+}
+
+/// Keyed cells which declare dependencies on other cells of the graph. Releasing or
+/// invalidating a cell cascades to everything depending on it, in topological order
+/// (a cell is handled after all of its affected dependencies). This is the base for derived
+/// data caches where results computed from a value become stale together with it.
+#[derive(Debug)]
+pub struct CellGraph<K, T> {
+    nodes: HashMap<K, Node<K, T>>,
+}
+
+impl<K: Eq + Hash + Clone, T> CellGraph<K, T> {
+    /// Creates an empty graph.
+    pub fn new() -> Self {
+        CellGraph {
+            nodes: HashMap::new(),
+        }
+    }
+
+    /// Returns the number of cells.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns 'true' when there are no cells.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Adds a cell depending on the cells `upstream`. An existing cell under `key` is
+    /// replaced, its dependents are kept. Fails when one of the upstream keys is unknown or
+    /// would close a cycle, the graph is left unchanged then.
+    pub fn insert(
+        &mut self,
+        key: K,
+        cell: RCell<T>,
+        upstream: impl IntoIterator<Item = K>,
+    ) -> Result<(), GraphError> {
+        let upstream: HashSet<K> = upstream.into_iter().collect();
+        for up in &upstream {
+            if !self.nodes.contains_key(up) {
+                return Err(GraphError::UnknownKey);
+            }
+            if *up == key || self.reachable(&key).contains(up) {
+                return Err(GraphError::Cycle);
+            }
+        }
+        let dependents = match self.nodes.remove(&key) {
+            Some(old) => {
+                for up in &old.upstream {
+                    if let Some(node) = self.nodes.get_mut(up) {
+                        node.dependents.remove(&key);
+                    }
+                }
+                old.dependents
+            }
+            None => HashSet::new(),
+        };
+        for up in &upstream {
+            if let Some(node) = self.nodes.get_mut(up) {
+                node.dependents.insert(key.clone());
+            }
+        }
+        self.nodes.insert(
+            key,
+            Node {
+                cell,
+                upstream,
+                dependents,
+            },
+        );
+        Ok(())
+    }
+
+    /// Declares that `dependent` depends on `upstream`.
+    pub fn add_dependency(&mut self, dependent: &K, upstream: &K) -> Result<(), GraphError> {
+        if !self.nodes.contains_key(dependent) || !self.nodes.contains_key(upstream) {
+            return Err(GraphError::UnknownKey);
+        }
+        if dependent == upstream || self.reachable(dependent).contains(upstream) {
+            return Err(GraphError::Cycle);
+        }
+        if let Some(node) = self.nodes.get_mut(dependent) {
+            node.upstream.insert(upstream.clone());
+        }
+        if let Some(node) = self.nodes.get_mut(upstream) {
+            node.dependents.insert(dependent.clone());
+        }
+        Ok(())
+    }
+
+    /// Removes a cell from the graph and returns it. The dependency edges to and from it are
+    /// dropped, the cells it depends on and its dependents stay in the graph.
+    pub fn remove(&mut self, key: &K) -> Option<RCell<T>> {
+        let node = self.nodes.remove(key)?;
+        for up in &node.upstream {
+            if let Some(up) = self.nodes.get_mut(up) {
+                up.dependents.remove(key);
+            }
+        }
+        for dependent in &node.dependents {
+            if let Some(dependent) = self.nodes.get_mut(dependent) {
+                dependent.upstream.remove(key);
+            }
+        }
+        Some(node.cell)
+    }
+
+    /// Returns the cell for `key`.
+    pub fn get(&self, key: &K) -> Option<&RCell<T>> {
+        self.nodes.get(key).map(|node| &node.cell)
+    }
+
+    /// Returns the cell for `key` mutably.
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut RCell<T>> {
+        self.nodes.get_mut(key).map(|node| &mut node.cell)
+    }
+
+    /// Tries to get a `Strong<T>` from a cell, see `RCell::request()`.
+    pub fn request(&self, key: &K) -> Option<Strong<T>> {
+        self.get(key)?.request()
+    }
+
+    /// Iterates over the direct dependencies of a cell.
+    pub fn upstream(&self, key: &K) -> impl Iterator<Item = &K> + '_ {
+        self.nodes
+            .get(key)
+            .into_iter()
+            .flat_map(|node| &node.upstream)
+    }
+
+    /// Iterates over the cells directly depending on a cell.
+    pub fn dependents(&self, key: &K) -> impl Iterator<Item = &K> + '_ {
+        self.nodes
+            .get(key)
+            .into_iter()
+            .flat_map(|node| &node.dependents)
+    }
+
+    /// Returns `key` and all cells depending on it transitively, in topological order.
+    pub fn cascade(&self, key: &K) -> Vec<K> {
+        let reachable = self.reachable(key);
+        let mut pending: HashMap<&K, usize> = reachable
+            .iter()
+            .map(|k| {
+                let count = self.nodes[k]
+                    .upstream
+                    .iter()
+                    .filter(|up| reachable.contains(*up))
+                    .count();
+                (k, count)
+            })
+            .collect();
+        let mut ready: VecDeque<&K> = pending
+            .iter()
+            .filter(|(_, count)| **count == 0)
+            .map(|(k, _)| *k)
+            .collect();
+        let mut order = Vec::with_capacity(reachable.len());
+        while let Some(k) = ready.pop_front() {
+            order.push(k.clone());
+            for dependent in &self.nodes[k].dependents {
+                if let Some(count) = pending.get_mut(dependent) {
+                    *count -= 1;
+                    if *count == 0 {
+                        ready.push_back(dependent);
+                    }
+                }
+            }
+        }
+        order
+    }
+
+    /// Releases `key` and everything depending on it. Returns the affected keys in the order
+    /// they were released.
+    pub fn release(&mut self, key: &K) -> Vec<K> {
+        let order = self.cascade(key);
+        for k in &order {
+            if let Some(node) = self.nodes.get_mut(k) {
+                node.cell.release();
+            }
+        }
+        order
+    }
+
+    /// Removes the references of `key` and everything depending on it, their cells become
+    /// empty. Returns the affected keys in the order they were invalidated.
+    pub fn invalidate(&mut self, key: &K) -> Vec<K> {
+        let order = self.cascade(key);
+        for k in &order {
+            if let Some(node) = self.nodes.get_mut(k) {
+                node.cell.remove();
+            }
+        }
+        order
+    }
+
+    fn reachable(&self, key: &K) -> HashSet<K> {
+        let mut seen = HashSet::new();
+        let mut stack = vec![key];
+        while let Some(k) = stack.pop() {
+            if let Some(node) = self.nodes.get(k) {
+                if seen.insert(k.clone()) {
+                    stack.extend(&node.dependents);
+                }
+            }
+        }
+        seen
+    }
+}
+
+impl<K: Eq + Hash + Clone, T> Default for CellGraph<K, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CellGraph, GraphError};
+    use crate::RCell;
+
+    #[test]
+    fn cascade() {
+        let mut graph = CellGraph::new();
+        graph.insert("source", RCell::new(1), []).unwrap();
+        graph.insert("parsed", RCell::new(2), ["source"]).unwrap();
+        graph.insert("other", RCell::new(3), []).unwrap();
+        graph
+            .insert("output", RCell::new(4), ["parsed", "source", "other"])
+            .unwrap();
+
+        assert_eq!(graph.cascade(&"source"), vec!["source", "parsed", "output"]);
+        assert_eq!(graph.cascade(&"other"), vec!["other", "output"]);
+
+        let kept = graph.request(&"parsed").unwrap();
+        assert_eq!(graph.release(&"parsed"), vec!["parsed", "output"]);
+        assert_eq!(graph.request(&"output"), None);
+        assert_eq!(graph.request(&"parsed"), Some(kept));
+        assert!(graph.get(&"source").unwrap().retained());
+
+        assert_eq!(graph.invalidate(&"source").len(), 3);
+        assert_eq!(graph.request(&"parsed"), None);
+        assert!(graph.get(&"other").unwrap().retained());
+    }
+
+    #[test]
+    fn errors() {
+        let mut graph = CellGraph::new();
+        graph.insert(1, RCell::new(()), []).unwrap();
+        graph.insert(2, RCell::new(()), [1]).unwrap();
+        assert_eq!(
+            graph.insert(3, RCell::new(()), [4]),
+            Err(GraphError::UnknownKey)
+        );
+        assert_eq!(graph.add_dependency(&1, &2), Err(GraphError::Cycle));
+        assert_eq!(graph.insert(1, RCell::new(()), [2]), Err(GraphError::Cycle));
+        assert_eq!(graph.add_dependency(&1, &1), Err(GraphError::Cycle));
+
+        graph.remove(&1);
+        assert_eq!(graph.upstream(&2).count(), 0);
+        graph.insert(1, RCell::new(()), [2]).unwrap();
+        assert_eq!(graph.dependents(&2).collect::<Vec<_>>(), vec![&1]);
+    }
+}
@@ -0,0 +1,157 @@
+//! RAII guards which retain or release a RCell for a scope.
+
+use std::ops::{Deref, DerefMut};
+
+use crate::{RCell, Strong};
+
+impl<T: ?Sized> RCell<T> {
+    /// Retains the RCell for the lifetime of the returned guard, see `retain()`. When the
+    /// guard gets dropped a previously released cell is released again.
+    pub fn retain_scoped(&mut self) -> RetainGuard<'_, T> {
+        let was_retained = self.retained();
+        let strong = self.retain();
+        RetainGuard {
+            cell: self,
+            strong,
+            was_retained,
+        }
+    }
+
+    /// Releases the RCell for the lifetime of the returned guard, see `release()`. When the
+    /// guard gets dropped a previously retained cell is retained again if the value is still
+    /// alive. This allows eviction of the value while the guard exists.
+    pub fn release_scoped(&mut self) -> ReleaseGuard<'_, T> {
+        let was_retained = self.retained();
+        self.release();
+        ReleaseGuard {
+            cell: self,
+            was_retained,
+        }
+    }
+}
+
+/// Guard returned by `RCell::retain_scoped()`, releases the cell when dropped unless it was
+/// retained before.
+#[derive(Debug)]
+#[must_use]
+pub struct RetainGuard<'a, T: ?Sized> {
+    cell: &'a mut RCell<T>,
+    strong: Option<Strong<T>>,
+    was_retained: bool,
+}
+
+impl<T: ?Sized> RetainGuard<'_, T> {
+    /// Returns the strong reference obtained when the guard was created, `None` when the value
+    /// was not alive.
+    pub fn strong(&self) -> Option<&Strong<T>> {
+        self.strong.as_ref()
+    }
+}
+
+impl<T: ?Sized> Deref for RetainGuard<'_, T> {
+    type Target = RCell<T>;
+
+    fn deref(&self) -> &RCell<T> {
+        self.cell
+    }
+}
+
+impl<T: ?Sized> DerefMut for RetainGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut RCell<T> {
+        self.cell
+    }
+}
+
+impl<T: ?Sized> Drop for RetainGuard<'_, T> {
+    fn drop(&mut self) {
+        self.strong = None;
+        if !self.was_retained {
+            self.cell.release();
+        }
+    }
+}
+
+/// Guard returned by `RCell::release_scoped()`, retains the cell again when dropped.
+#[derive(Debug)]
+#[must_use]
+pub struct ReleaseGuard<'a, T: ?Sized> {
+    cell: &'a mut RCell<T>,
+    was_retained: bool,
+}
+
+impl<T: ?Sized> Deref for ReleaseGuard<'_, T> {
+    type Target = RCell<T>;
+
+    fn deref(&self) -> &RCell<T> {
+        self.cell
+    }
+}
+
+impl<T: ?Sized> DerefMut for ReleaseGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut RCell<T> {
+        self.cell
+    }
+}
+
+impl<T: ?Sized> Drop for ReleaseGuard<'_, T> {
+    fn drop(&mut self) {
+        if self.was_retained {
+            self.cell.retain();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{RCell, Strong};
+
+    #[test]
+    fn retain_scoped() {
+        let strong = Strong::new("foobar");
+        let mut rcell = RCell::from(Strong::downgrade(&strong));
+        {
+            let guard = rcell.retain_scoped();
+            assert!(guard.retained());
+            assert_eq!(**guard.strong().unwrap(), "foobar");
+        }
+        assert!(!rcell.retained());
+        assert_eq!(*rcell.request().unwrap(), "foobar");
+        drop(strong);
+        let guard = rcell.retain_scoped();
+        assert!(guard.strong().is_none());
+    }
+
+    #[test]
+    fn retain_scoped_strong() {
+        let mut rcell = RCell::new("foobar");
+        drop(rcell.retain_scoped());
+        assert!(rcell.retained());
+        assert_eq!(*rcell.request().unwrap(), "foobar");
+    }
+
+    #[test]
+    fn release_scoped() {
+        let mut rcell = RCell::new("foobar");
+        let strong = rcell.request().unwrap();
+        {
+            let guard = rcell.release_scoped();
+            assert!(!guard.retained());
+        }
+        assert!(rcell.retained());
+
+        drop(strong);
+        {
+            let _guard = rcell.release_scoped();
+        }
+        assert!(!rcell.retained());
+        assert_eq!(rcell.request(), None);
+    }
+
+    #[test]
+    fn release_scoped_weak() {
+        let strong = Strong::new("foobar");
+        let mut rcell = RCell::from(Strong::downgrade(&strong));
+        drop(rcell.release_scoped());
+        assert!(!rcell.retained());
+    }
+}
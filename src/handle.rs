@@ -0,0 +1,166 @@
+//! Small integer handles to cells for scripting and embedding.
+
+use std::collections::BTreeSet;
+
+use crate::{RCell, Strong};
+
+#[derive(Debug)]
+struct Slot<T: ?Sized> {
+    cell: RCell<T>,
+    checkouts: usize,
+}
+
+/// Maps small integer handles to cells, so that scripting languages can refer to Rust side
+/// objects by plain numbers. Like file descriptors the lowest free handle is assigned. A
+/// cell is retained while it is checked out and released when the last checkout is
+/// returned, handles to dropped values just fail to check out.
+#[derive(Debug)]
+pub struct HandleTable<T: ?Sized> {
+    slots: Vec<Option<Slot<T>>>,
+    free: BTreeSet<u32>,
+}
+
+impl<T: ?Sized> Default for HandleTable<T> {
+    fn default() -> Self {
+        HandleTable {
+            slots: Vec::new(),
+            free: BTreeSet::new(),
+        }
+    }
+}
+
+impl<T: ?Sized> HandleTable<T> {
+    /// Creates an empty table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of handles in use.
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    /// Returns 'true' when no handles are in use.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn slot(&mut self, handle: u32) -> Option<&mut Slot<T>> {
+        self.slots.get_mut(handle as usize)?.as_mut()
+    }
+
+    /// Adds a cell and returns its handle.
+    pub fn insert(&mut self, cell: RCell<T>) -> u32 {
+        let slot = Some(Slot { cell, checkouts: 0 });
+        match self.free.pop_first() {
+            Some(handle) => {
+                self.slots[handle as usize] = slot;
+                handle
+            }
+            None => {
+                self.slots.push(slot);
+                (self.slots.len() - 1) as u32
+            }
+        }
+    }
+
+    /// Returns the cell behind `handle`.
+    pub fn get(&self, handle: u32) -> Option<&RCell<T>> {
+        Some(&self.slots.get(handle as usize)?.as_ref()?.cell)
+    }
+
+    /// Retains the cell behind `handle` and returns its value. Every successful checkout
+    /// has to be matched by a `checkin()`.
+    pub fn checkout(&mut self, handle: u32) -> Option<Strong<T>> {
+        let slot = self.slot(handle)?;
+        let strong = slot.cell.retain()?;
+        slot.checkouts += 1;
+        Some(strong)
+    }
+
+    /// Returns a checkout, the cell is released when no checkouts are left. Returns 'false'
+    /// when `handle` was not checked out.
+    pub fn checkin(&mut self, handle: u32) -> bool {
+        match self.slot(handle) {
+            Some(slot) if slot.checkouts > 0 => {
+                slot.checkouts -= 1;
+                if slot.checkouts == 0 {
+                    slot.cell.release();
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the number of outstanding checkouts of `handle`.
+    pub fn checkouts(&self, handle: u32) -> usize {
+        self.slots
+            .get(handle as usize)
+            .and_then(Option::as_ref)
+            .map_or(0, |slot| slot.checkouts)
+    }
+
+    /// Frees `handle` and returns its cell. The handle may be assigned again.
+    pub fn remove(&mut self, handle: u32) -> Option<RCell<T>> {
+        let slot = self.slots.get_mut(handle as usize)?.take()?;
+        self.free.insert(handle);
+        Some(slot.cell)
+    }
+
+    /// Frees the handles which are not checked out and whose value got dropped. Returns the
+    /// number of freed handles.
+    pub fn purge(&mut self) -> usize {
+        let mut purged = 0;
+        for (handle, slot) in self.slots.iter_mut().enumerate() {
+            if slot
+                .as_ref()
+                .is_some_and(|slot| slot.checkouts == 0 && slot.cell.refcount() == 0)
+            {
+                *slot = None;
+                self.free.insert(handle as u32);
+                purged += 1;
+            }
+        }
+        purged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HandleTable;
+    use crate::{RCell, Strong};
+
+    #[test]
+    fn checkout() {
+        let strong = Strong::new("foo");
+        let mut table = HandleTable::new();
+        let handle = table.insert(RCell::from(Strong::downgrade(&strong)));
+        assert_eq!(handle, 0);
+
+        assert_eq!(table.checkout(handle), Some(strong.clone()));
+        assert_eq!(table.checkout(handle), Some(strong.clone()));
+        drop(strong);
+        assert!(table.checkin(handle));
+        assert_eq!(table.checkouts(handle), 1);
+        assert!(table.get(handle).unwrap().retained());
+        assert!(table.checkin(handle));
+        assert!(!table.checkin(handle));
+        assert_eq!(table.checkout(handle), None);
+        assert_eq!(table.purge(), 1);
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn reuse() {
+        let mut table = HandleTable::new();
+        let cells: Vec<_> = (0..3).map(|i| table.insert(RCell::new(i))).collect();
+        assert_eq!(cells, [0, 1, 2]);
+        assert!(table.remove(1).is_some());
+        assert!(table.remove(0).is_some());
+        assert_eq!(table.insert(RCell::new(3)), 0);
+        assert_eq!(table.insert(RCell::new(4)), 1);
+        assert_eq!(table.insert(RCell::new(5)), 3);
+        assert_eq!(table.len(), 4);
+    }
+}
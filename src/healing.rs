@@ -0,0 +1,106 @@
+//! Cell rebuilding its value whenever it got dropped.
+
+use std::ops::Deref;
+
+use crate::{RCell, Replace, Strong};
+
+/// A RCell which rebuilds its value from a descriptor whenever it finds the value dropped,
+/// callers of `request()` never observe a miss. The descriptor is a lightweight recipe for
+/// the value such as a path or an id, use `()` when the rebuild function needs none.
+/// Derefs to the inner `RCell<T>` for read only access.
+pub struct HealingRCell<T, D, F> {
+    cell: RCell<T>,
+    descriptor: D,
+    rebuild: F,
+    rebuilds: u64,
+}
+
+impl<T, D: std::fmt::Debug, F> std::fmt::Debug for HealingRCell<T, D, F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HealingRCell")
+            .field("cell", &self.cell)
+            .field("descriptor", &self.descriptor)
+            .field("rebuilds", &self.rebuilds)
+            .finish()
+    }
+}
+
+impl<T, D, F: Fn(&D) -> T> HealingRCell<T, D, F> {
+    /// Creates an empty HealingRCell, the value is built on the first request.
+    pub fn new(descriptor: D, rebuild: F) -> Self {
+        Self::from_rcell(RCell::empty(), descriptor, rebuild)
+    }
+
+    /// Wraps an existing RCell, `rebuild` is used once its value got dropped.
+    pub fn from_rcell(cell: RCell<T>, descriptor: D, rebuild: F) -> Self {
+        HealingRCell {
+            cell,
+            descriptor,
+            rebuild,
+            rebuilds: 0,
+        }
+    }
+
+    /// Returns the value, rebuilds and retains it when it got dropped.
+    pub fn request(&mut self) -> Strong<T> {
+        if let Some(strong) = self.cell.request() {
+            return strong;
+        }
+        let strong = Strong::new((self.rebuild)(&self.descriptor));
+        self.cell.replace(strong.clone());
+        self.rebuilds += 1;
+        strong
+    }
+
+    /// Returns the descriptor.
+    pub fn descriptor(&self) -> &D {
+        &self.descriptor
+    }
+
+    /// Returns how often the value was (re)built.
+    pub fn rebuilds(&self) -> u64 {
+        self.rebuilds
+    }
+
+    /// Releases the cell, see `RCell::release()`.
+    pub fn release(&mut self) {
+        self.cell.release();
+    }
+
+    /// Drops the reference, the next request always builds a new value, even when the old
+    /// one is still kept alive elsewhere. Use `release()` to find a live value again.
+    pub fn remove(&mut self) {
+        self.cell.remove();
+    }
+}
+
+impl<T, D, F> Deref for HealingRCell<T, D, F> {
+    type Target = RCell<T>;
+
+    fn deref(&self) -> &RCell<T> {
+        &self.cell
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HealingRCell;
+
+    #[test]
+    fn rebuild() {
+        let mut cell = HealingRCell::new(3, |n: &usize| vec![0u8; *n]);
+        assert_eq!(cell.request().len(), 3);
+        assert_eq!(cell.rebuilds(), 1);
+
+        let kept = cell.request();
+        cell.release();
+        assert_eq!(cell.request(), kept);
+        assert_eq!(cell.rebuilds(), 1);
+
+        drop(kept);
+        cell.release();
+        assert_eq!(cell.request().len(), *cell.descriptor());
+        assert_eq!(cell.rebuilds(), 2);
+        assert!(cell.retained());
+    }
+}
@@ -0,0 +1,120 @@
+//! Per cell audit trail of the last state transitions.
+//!
+//! ```
+//! use rcell::*;
+//!
+//! let mut cell = RCell::new(1);
+//! let value = cell.request();
+//! cell.release();
+//! let last = cell.history().last().unwrap();
+//! assert_eq!(last.operation, Operation::Release);
+//! assert_eq!(last.to, history::Kind::Weak);
+//! ```
+
+use std::collections::VecDeque;
+use std::thread::{self, ThreadId};
+use std::time::Instant;
+
+use crate::{Operation, RCell};
+
+/// Number of transitions kept per cell.
+pub const HISTORY_LEN: usize = 16;
+
+/// The kind of reference a RCell holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// Strong reference
+    Strong,
+    /// Weak reference
+    Weak,
+    /// Empty cell
+    Empty,
+}
+
+/// A recorded state transition.
+#[derive(Debug, Clone)]
+pub struct Transition {
+    /// The operation that caused the transition.
+    pub operation: Operation,
+    /// State before the operation.
+    pub from: Kind,
+    /// State after the operation.
+    pub to: Kind,
+    /// The thread which executed the operation.
+    pub thread: ThreadId,
+    /// When the operation happened.
+    pub time: Instant,
+}
+
+/// Ring buffer of the last `HISTORY_LEN` transitions.
+#[derive(Debug)]
+pub(crate) struct History(VecDeque<Transition>);
+
+impl History {
+    pub(crate) const fn new() -> Self {
+        History(VecDeque::new())
+    }
+
+    pub(crate) fn record(&mut self, operation: Operation, from: Kind, to: Kind) {
+        if self.0.len() == HISTORY_LEN {
+            self.0.pop_front();
+        }
+        self.0.push_back(Transition {
+            operation,
+            from,
+            to,
+            thread: thread::current().id(),
+            time: Instant::now(),
+        });
+    }
+}
+
+impl<T: ?Sized> RCell<T> {
+    /// Returns the last recorded state transitions of this cell, oldest first.
+    pub fn history(&self) -> impl DoubleEndedIterator<Item = &Transition> + '_ {
+        self.history.0.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Kind, HISTORY_LEN};
+    use crate::{Operation, RCell};
+
+    #[test]
+    fn transitions() {
+        let mut rcell = RCell::new("foobar");
+        let strong = rcell.request().unwrap();
+        rcell.release();
+        rcell.retain();
+        rcell.remove();
+        let history: Vec<_> = rcell
+            .history()
+            .map(|t| (t.operation, t.from, t.to))
+            .collect();
+        assert_eq!(
+            history,
+            vec![
+                (Operation::New, Kind::Empty, Kind::Strong),
+                (Operation::Release, Kind::Strong, Kind::Weak),
+                (Operation::Retain, Kind::Weak, Kind::Strong),
+                (Operation::Remove, Kind::Strong, Kind::Empty),
+            ]
+        );
+        assert_eq!(
+            rcell.history().last().unwrap().thread,
+            std::thread::current().id()
+        );
+        drop(strong);
+    }
+
+    #[test]
+    fn bounded() {
+        let mut rcell = RCell::new("foobar");
+        for _ in 0..HISTORY_LEN {
+            rcell.remove();
+        }
+        assert_eq!(rcell.history().count(), HISTORY_LEN);
+        assert_eq!(rcell.history().next().unwrap().operation, Operation::Remove);
+    }
+}
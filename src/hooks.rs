@@ -0,0 +1,172 @@
+//! Lifecycle callbacks for RCells.
+
+use std::ops::Deref;
+
+use crate::{RCell, Replace, Strong, Weak};
+
+/// Callbacks invoked by a `HookedRCell`. All methods default to doing nothing. Hooks can be
+/// stateful per cell or zero sized types implementing a policy for a whole type.
+pub trait RCellHooks<T: ?Sized> {
+    /// Called after the cell was retained successfully.
+    fn on_retain(&self, _strong: &Strong<T>) {}
+
+    /// Called after the cell was released.
+    fn on_release(&self, _cell: &RCell<T>) {}
+
+    /// Called after a new reference was stored in the cell.
+    fn on_replace(&self, _cell: &RCell<T>) {}
+
+    /// Called when the cell notices that its value got dropped. That is when releasing the
+    /// cell dropped the value or retaining failed because it was gone.
+    fn on_value_drop(&self) {}
+}
+
+/// No hooks.
+impl<T: ?Sized> RCellHooks<T> for () {}
+
+/// A RCell which invokes `RCellHooks` on its lifecycle operations. Derefs to the inner
+/// `RCell<T>` for read only access.
+#[derive(Debug, Default)]
+pub struct HookedRCell<T: ?Sized, H: RCellHooks<T>> {
+    cell: RCell<T>,
+    hooks: H,
+}
+
+impl<T, H: RCellHooks<T>> HookedRCell<T, H> {
+    /// Creates a new strong HookedRCell from the supplied value.
+    pub fn new(value: T, hooks: H) -> Self {
+        Self::from_rcell(RCell::new(value), hooks)
+    }
+}
+
+impl<T: ?Sized, H: RCellHooks<T>> HookedRCell<T, H> {
+    /// Wraps an existing RCell.
+    pub fn from_rcell(cell: RCell<T>, hooks: H) -> Self {
+        HookedRCell { cell, hooks }
+    }
+
+    /// Returns the hooks.
+    pub fn hooks(&self) -> &H {
+        &self.hooks
+    }
+
+    /// Tries to retain the cell, see `RCell::retain()`.
+    pub fn retain(&mut self) -> Option<Strong<T>> {
+        let had_value = !matches!(self.cell.state(), crate::State::Empty);
+        match self.cell.retain() {
+            Some(strong) => {
+                self.hooks.on_retain(&strong);
+                Some(strong)
+            }
+            None => {
+                if had_value {
+                    self.cell.remove();
+                    self.hooks.on_value_drop();
+                }
+                None
+            }
+        }
+    }
+
+    /// Releases the cell, see `RCell::release()`.
+    pub fn release(&mut self) {
+        let had_value = self.cell.refcount() > 0;
+        self.cell.release();
+        self.hooks.on_release(&self.cell);
+        if had_value && self.cell.refcount() == 0 {
+            self.hooks.on_value_drop();
+        }
+    }
+
+    /// Removes the reference from the cell, see `RCell::remove()`.
+    pub fn remove(&mut self) {
+        let unique = self.cell.is_unique();
+        self.cell.remove();
+        if unique {
+            self.hooks.on_value_drop();
+        }
+    }
+
+    /// Stores a new strong reference in the cell.
+    pub fn replace_strong(&mut self, strong: Strong<T>) {
+        self.cell.replace(strong);
+        self.hooks.on_replace(&self.cell);
+    }
+
+    /// Stores a new weak reference in the cell.
+    pub fn replace_weak(&mut self, weak: Weak<T>) {
+        self.cell.replace(weak);
+        self.hooks.on_replace(&self.cell);
+    }
+
+    /// Unwraps the inner RCell.
+    pub fn into_inner(self) -> RCell<T> {
+        self.cell
+    }
+}
+
+impl<T: ?Sized, H: RCellHooks<T>> Deref for HookedRCell<T, H> {
+    type Target = RCell<T>;
+
+    fn deref(&self) -> &RCell<T> {
+        &self.cell
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::{HookedRCell, RCellHooks};
+    use crate::{RCell, Strong};
+
+    #[derive(Default)]
+    struct Log(RefCell<Vec<&'static str>>);
+
+    impl<T> RCellHooks<T> for Log {
+        fn on_retain(&self, _strong: &Strong<T>) {
+            self.0.borrow_mut().push("retain");
+        }
+
+        fn on_release(&self, _cell: &RCell<T>) {
+            self.0.borrow_mut().push("release");
+        }
+
+        fn on_replace(&self, _cell: &RCell<T>) {
+            self.0.borrow_mut().push("replace");
+        }
+
+        fn on_value_drop(&self) {
+            self.0.borrow_mut().push("drop");
+        }
+    }
+
+    #[test]
+    fn hooks() {
+        let mut cell = HookedRCell::new(1, Log::default());
+        let strong = cell.request().unwrap();
+        cell.release();
+        cell.retain();
+        cell.release();
+        drop(strong);
+        assert_eq!(cell.retain(), None);
+        cell.replace_strong(Strong::new(2));
+        cell.release();
+        cell.replace_strong(Strong::new(3));
+        cell.remove();
+        assert_eq!(
+            *cell.hooks().0.borrow(),
+            vec![
+                "release", "retain", "release", "drop", "replace", "release", "drop", "replace",
+                "drop"
+            ]
+        );
+    }
+
+    #[test]
+    fn no_hooks() {
+        let mut cell = HookedRCell::new(1, ());
+        cell.release();
+        assert!(cell.into_inner().request().is_none());
+    }
+}
@@ -0,0 +1,157 @@
+//! Stable numeric identities for RCells.
+
+use std::collections::HashMap;
+
+use crate::{RCell, Strong};
+
+/// Identity of a cell in an `IdTable`. Ids are never reused within a table, a stale id can't
+/// alias a later entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RCellId(u64);
+
+impl RCellId {
+    /// Returns the numeric value, for sending ids over the network or to scripts.
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+
+    /// Recreates an id from its numeric value.
+    pub fn from_u64(id: u64) -> Self {
+        RCellId(id)
+    }
+}
+
+impl std::fmt::Display for RCellId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// Assigns stable `RCellId`s to cells and looks them up in both directions. Systems which
+/// refer to cached objects by compact ids (networking, scripting) can hand out the id and
+/// resolve it later, dropped values simply don't resolve anymore.
+#[derive(Debug)]
+pub struct IdTable<T: ?Sized> {
+    next_id: u64,
+    cells: HashMap<RCellId, RCell<T>>,
+    by_addr: HashMap<usize, RCellId>,
+}
+
+fn addr<T: ?Sized>(strong: &Strong<T>) -> usize {
+    Strong::as_ptr(strong) as *const () as usize
+}
+
+impl<T: ?Sized> IdTable<T> {
+    /// Creates an empty table.
+    pub fn new() -> Self {
+        IdTable {
+            next_id: 0,
+            cells: HashMap::new(),
+            by_addr: HashMap::new(),
+        }
+    }
+
+    /// Returns the number of entries.
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    /// Returns 'true' when there are no entries.
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    /// Adds a cell and returns its new id.
+    pub fn insert(&mut self, cell: RCell<T>) -> RCellId {
+        let id = RCellId(self.next_id);
+        self.next_id += 1;
+        if let Some(strong) = cell.request() {
+            self.by_addr.insert(addr(&strong), id);
+        }
+        self.cells.insert(id, cell);
+        id
+    }
+
+    /// Returns the cell for `id`.
+    pub fn get(&self, id: RCellId) -> Option<&RCell<T>> {
+        self.cells.get(&id)
+    }
+
+    /// Returns the cell for `id` mutably. Values stored into it this way are not found by
+    /// `id_of()`.
+    pub fn get_mut(&mut self, id: RCellId) -> Option<&mut RCell<T>> {
+        self.cells.get_mut(&id)
+    }
+
+    /// Tries to get a `Strong<T>` for `id`, see `RCell::request()`.
+    pub fn request(&self, id: RCellId) -> Option<Strong<T>> {
+        self.get(id)?.request()
+    }
+
+    /// Returns the id of the entry holding `strong`'s value.
+    pub fn id_of(&self, strong: &Strong<T>) -> Option<RCellId> {
+        let id = *self.by_addr.get(&addr(strong))?;
+        // the address may have been reused by another allocation
+        self.request(id)
+            .filter(|value| Strong::ptr_eq(value, strong))
+            .map(|_| id)
+    }
+
+    /// Removes the entry for `id` and returns its cell.
+    pub fn remove(&mut self, id: RCellId) -> Option<RCell<T>> {
+        let cell = self.cells.remove(&id)?;
+        self.by_addr.retain(|_, other| *other != id);
+        Some(cell)
+    }
+
+    /// Removes the entries whose value got dropped, returns how many were removed.
+    pub fn purge(&mut self) -> usize {
+        let before = self.cells.len();
+        self.cells.retain(|_, cell| cell.refcount() > 0);
+        let cells = &self.cells;
+        self.by_addr.retain(|_, id| cells.contains_key(id));
+        before - self.cells.len()
+    }
+
+    /// Iterates over all entries.
+    pub fn iter(&self) -> impl Iterator<Item = (RCellId, &RCell<T>)> + '_ {
+        self.cells.iter().map(|(id, cell)| (*id, cell))
+    }
+}
+
+impl<T: ?Sized> Default for IdTable<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IdTable, RCellId};
+    use crate::{RCell, Strong};
+
+    #[test]
+    fn round_trip() {
+        let mut table = IdTable::new();
+        let a = table.insert(RCell::new("a"));
+        let keep = Strong::new("b");
+        let b = table.insert(RCell::from(Strong::downgrade(&keep)));
+        assert_ne!(a, b);
+        assert_eq!(RCellId::from_u64(b.as_u64()), b);
+        assert_eq!(format!("{a}"), "#0");
+
+        let value = table.request(a).unwrap();
+        assert_eq!(table.id_of(&value), Some(a));
+        assert_eq!(table.id_of(&keep), Some(b));
+        assert_eq!(table.id_of(&Strong::new("a")), None);
+
+        drop(keep);
+        assert_eq!(table.request(b), None);
+        assert_eq!(table.purge(), 1);
+        assert_eq!(table.remove(a).unwrap().request(), Some(value.clone()));
+        assert_eq!(table.id_of(&value), None);
+        assert!(table.is_empty());
+        // ids are not reused
+        assert_eq!(table.insert(RCell::new("c")).as_u64(), 2);
+    }
+}
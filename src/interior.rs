@@ -0,0 +1,308 @@
+//! Passthrough helpers for RCells holding interior mutable types.
+//!
+//! The returned guards own the upgraded `Strong<T>` so the value stays alive for as long as
+//! it is borrowed, even when the cell gets released meanwhile. They do not borrow the cell
+//! itself.
+
+use std::cell::{Ref, RefCell, RefMut};
+use std::ops::{Deref, DerefMut};
+#[cfg(feature = "sync")]
+use std::sync::{
+    LockResult, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard,
+};
+
+use crate::{RCell, Strong};
+
+impl<T> RCell<RefCell<T>> {
+    /// Immutably borrows the value when it is alive, see `RefCell::borrow()`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently mutably borrowed.
+    pub fn borrow<'a>(&self) -> Option<RCellRef<'a, T>>
+    where
+        T: 'a,
+    {
+        let strong = self.request()?;
+        // SAFETY: the RefCell lives in the allocation kept alive by 'strong' which is
+        // stored in the guard and dropped after the borrow
+        let borrow = unsafe { &*Strong::as_ptr(&strong) }.borrow();
+        Some(RCellRef {
+            borrow,
+            _strong: strong,
+        })
+    }
+
+    /// Mutably borrows the value when it is alive, see `RefCell::borrow_mut()`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently borrowed.
+    pub fn borrow_mut<'a>(&self) -> Option<RCellRefMut<'a, T>>
+    where
+        T: 'a,
+    {
+        let strong = self.request()?;
+        // SAFETY: see borrow()
+        let borrow = unsafe { &*Strong::as_ptr(&strong) }.borrow_mut();
+        Some(RCellRefMut {
+            borrow,
+            _strong: strong,
+        })
+    }
+}
+
+/// Immutable borrow of a `RCell<RefCell<T>>` which keeps the value alive.
+#[derive(Debug)]
+pub struct RCellRef<'a, T> {
+    // must be declared first to be dropped before the strong reference it borrows from
+    borrow: Ref<'a, T>,
+    _strong: Strong<RefCell<T>>,
+}
+
+impl<T> Deref for RCellRef<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.borrow
+    }
+}
+
+/// Mutable borrow of a `RCell<RefCell<T>>` which keeps the value alive.
+#[derive(Debug)]
+pub struct RCellRefMut<'a, T> {
+    // must be declared first to be dropped before the strong reference it borrows from
+    borrow: RefMut<'a, T>,
+    _strong: Strong<RefCell<T>>,
+}
+
+impl<T> Deref for RCellRefMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.borrow
+    }
+}
+
+impl<T> DerefMut for RCellRefMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.borrow
+    }
+}
+
+#[cfg(feature = "sync")]
+impl<T> RCell<Mutex<T>> {
+    /// Locks the mutex when the value is alive, see `Mutex::lock()`.
+    pub fn lock<'a>(&self) -> Option<LockResult<RCellMutexGuard<'a, T>>>
+    where
+        T: 'a,
+    {
+        let strong = self.request()?;
+        // SAFETY: see RCell<RefCell<T>>::borrow()
+        let mutex = unsafe { &*Strong::as_ptr(&strong) };
+        Some(match mutex.lock() {
+            Ok(guard) => Ok(RCellMutexGuard {
+                guard,
+                _strong: strong,
+            }),
+            Err(poison) => Err(PoisonError::new(RCellMutexGuard {
+                guard: poison.into_inner(),
+                _strong: strong,
+            })),
+        })
+    }
+}
+
+/// Lock guard of a `RCell<Mutex<T>>` which keeps the value alive.
+#[cfg(feature = "sync")]
+#[derive(Debug)]
+pub struct RCellMutexGuard<'a, T> {
+    // must be declared first to be dropped before the strong reference it borrows from
+    guard: MutexGuard<'a, T>,
+    _strong: Strong<Mutex<T>>,
+}
+
+#[cfg(feature = "sync")]
+impl<T> Deref for RCellMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+#[cfg(feature = "sync")]
+impl<T> DerefMut for RCellMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+#[cfg(feature = "sync")]
+impl<T> RCell<RwLock<T>> {
+    /// Locks the value for reading when it is alive, see `RwLock::read()`.
+    pub fn read<'a>(&self) -> Option<LockResult<RCellReadGuard<'a, T>>>
+    where
+        T: 'a,
+    {
+        let strong = self.request()?;
+        // SAFETY: see RCell<RefCell<T>>::borrow()
+        let lock = unsafe { &*Strong::as_ptr(&strong) };
+        Some(match lock.read() {
+            Ok(guard) => Ok(RCellReadGuard {
+                guard,
+                _strong: strong,
+            }),
+            Err(poison) => Err(PoisonError::new(RCellReadGuard {
+                guard: poison.into_inner(),
+                _strong: strong,
+            })),
+        })
+    }
+
+    /// Locks the value for writing when it is alive, see `RwLock::write()`.
+    pub fn write<'a>(&self) -> Option<LockResult<RCellWriteGuard<'a, T>>>
+    where
+        T: 'a,
+    {
+        let strong = self.request()?;
+        // SAFETY: see RCell<RefCell<T>>::borrow()
+        let lock = unsafe { &*Strong::as_ptr(&strong) };
+        Some(match lock.write() {
+            Ok(guard) => Ok(RCellWriteGuard {
+                guard,
+                _strong: strong,
+            }),
+            Err(poison) => Err(PoisonError::new(RCellWriteGuard {
+                guard: poison.into_inner(),
+                _strong: strong,
+            })),
+        })
+    }
+}
+
+/// Read guard of a `RCell<RwLock<T>>` which keeps the value alive.
+#[cfg(feature = "sync")]
+#[derive(Debug)]
+pub struct RCellReadGuard<'a, T> {
+    // must be declared first to be dropped before the strong reference it borrows from
+    guard: RwLockReadGuard<'a, T>,
+    _strong: Strong<RwLock<T>>,
+}
+
+#[cfg(feature = "sync")]
+impl<T> Deref for RCellReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+/// Write guard of a `RCell<RwLock<T>>` which keeps the value alive.
+#[cfg(feature = "sync")]
+#[derive(Debug)]
+pub struct RCellWriteGuard<'a, T> {
+    // must be declared first to be dropped before the strong reference it borrows from
+    guard: RwLockWriteGuard<'a, T>,
+    _strong: Strong<RwLock<T>>,
+}
+
+#[cfg(feature = "sync")]
+impl<T> Deref for RCellWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+#[cfg(feature = "sync")]
+impl<T> DerefMut for RCellWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use crate::RCell;
+
+    #[test]
+    fn borrow() {
+        let rcell = RCell::new(RefCell::new(vec![1]));
+        rcell.borrow_mut().unwrap().push(2);
+        assert_eq!(*rcell.borrow().unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn borrow_keeps_alive() {
+        let mut rcell = RCell::new(RefCell::new(String::from("foo")));
+        let mut borrow = rcell.borrow_mut().unwrap();
+        borrow.push_str("bar");
+        drop(borrow);
+        let borrow = rcell.borrow().unwrap();
+        rcell.release();
+        assert!(!rcell.retained());
+        assert_eq!(*borrow, "foobar");
+        drop(borrow);
+        assert!(rcell.borrow().is_none());
+    }
+
+    #[test]
+    #[should_panic]
+    fn borrow_conflict() {
+        let rcell = RCell::new(RefCell::new(1));
+        let _borrow = rcell.borrow_mut().unwrap();
+        let _other = rcell.borrow();
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn lock() {
+        use std::sync::Mutex;
+
+        let mut rcell = RCell::new(Mutex::new(1));
+        let mut guard = rcell.lock().unwrap().unwrap();
+        *guard += 1;
+        rcell.release();
+        assert_eq!(*guard, 2);
+        drop(guard);
+        assert!(rcell.lock().is_none());
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn lock_poisoned() {
+        use std::sync::Mutex;
+
+        let rcell = RCell::new(Mutex::new(1));
+        let strong = rcell.request().unwrap();
+        let _ = std::thread::spawn(move || {
+            let _guard = strong.lock();
+            panic!("poison");
+        })
+        .join();
+        assert_eq!(*rcell.lock().unwrap().unwrap_err().into_inner(), 1);
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn read_write() {
+        use std::sync::RwLock;
+
+        let mut rcell = RCell::new(RwLock::new(1));
+        *rcell.write().unwrap().unwrap() += 1;
+        let first = rcell.read().unwrap().unwrap();
+        let second = rcell.read().unwrap().unwrap();
+        rcell.release();
+        assert_eq!(*first, 2);
+        assert_eq!(*second, 2);
+        drop(first);
+        drop(second);
+        assert!(rcell.read().is_none());
+        assert!(rcell.write().is_none());
+    }
+}
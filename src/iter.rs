@@ -0,0 +1,120 @@
+//! Iterator adapters over RCells.
+
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+
+use crate::{RCell, Strong};
+
+/// Iterator yielding the values of live RCells, returned by `UpgradeExt::upgraded()`.
+#[derive(Debug, Clone)]
+pub struct UpgradeIter<I> {
+    iter: I,
+}
+
+impl<'a, T: ?Sized + 'a, I: Iterator<Item = &'a RCell<T>>> Iterator for UpgradeIter<I> {
+    type Item = Strong<T>;
+
+    fn next(&mut self) -> Option<Strong<T>> {
+        self.iter.by_ref().find_map(RCell::request)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.iter.size_hint().1)
+    }
+}
+
+/// Extension trait for iterators over `&RCell<T>`.
+pub trait UpgradeExt: Sized {
+    /// Yields a `Strong<T>` for every live cell, dead and empty cells are skipped.
+    fn upgraded(self) -> UpgradeIter<Self>;
+}
+
+impl<'a, T: ?Sized + 'a, I: Iterator<Item = &'a RCell<T>>> UpgradeExt for I {
+    fn upgraded(self) -> UpgradeIter<Self> {
+        UpgradeIter { iter: self }
+    }
+}
+
+/// Collections of RCells which can remove their dead entries, those whose value got dropped
+/// or which are empty, in one pass.
+pub trait DrainDead {
+    /// The removed entries.
+    type Item;
+
+    /// Removes the dead entries and returns them.
+    fn drain_dead(&mut self) -> Vec<Self::Item>;
+}
+
+impl<T: ?Sized> DrainDead for Vec<RCell<T>> {
+    type Item = RCell<T>;
+
+    fn drain_dead(&mut self) -> Vec<RCell<T>> {
+        let mut dead = Vec::new();
+        self.retain_mut(|cell| {
+            if cell.refcount() > 0 {
+                return true;
+            }
+            dead.push(std::mem::take(cell));
+            false
+        });
+        dead
+    }
+}
+
+impl<K: Eq + Hash, T: ?Sized, S: BuildHasher> DrainDead for HashMap<K, RCell<T>, S> {
+    type Item = (K, RCell<T>);
+
+    fn drain_dead(&mut self) -> Vec<(K, RCell<T>)> {
+        drain_map(self, |cell| cell.refcount() == 0)
+    }
+}
+
+/// Removes the entries whose value matches `dead` from a map and returns them. The keys
+/// can't be moved out in `HashMap::retain()`, the map is drained and the others are put
+/// back, only when there is something to remove.
+pub(crate) fn drain_map<K: Eq + Hash, V, S: BuildHasher>(
+    map: &mut HashMap<K, V, S>,
+    dead: impl Fn(&V) -> bool,
+) -> Vec<(K, V)> {
+    if !map.values().any(&dead) {
+        return Vec::new();
+    }
+    let (dead, live): (Vec<_>, Vec<_>) = map.drain().partition(|(_, value)| dead(value));
+    map.extend(live);
+    dead
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DrainDead, UpgradeExt};
+    use crate::{RCell, Strong};
+
+    #[test]
+    fn upgraded() {
+        let keep = Strong::new(2);
+        let mut cells = vec![RCell::new(1), RCell::from(keep.clone()), RCell::empty()];
+        cells[1].release();
+        cells.push(RCell::from(Strong::downgrade(&Strong::new(3))));
+        let values: Vec<_> = cells.iter().upgraded().map(|strong| *strong).collect();
+        assert_eq!(values, vec![1, 2]);
+    }
+
+    #[test]
+    fn drain_dead() {
+        let keep = Strong::new(2);
+        let mut cells = vec![
+            RCell::new(1),
+            RCell::from(Strong::downgrade(&keep)),
+            RCell::empty(),
+            RCell::from(Strong::downgrade(&Strong::new(3))),
+        ];
+        assert_eq!(cells.drain_dead().len(), 2);
+        assert_eq!(cells.len(), 2);
+
+        let mut map: std::collections::HashMap<_, _> = cells.into_iter().enumerate().collect();
+        drop(keep);
+        let dead: Vec<_> = map.drain_dead().into_iter().map(|(key, _)| key).collect();
+        assert_eq!(dead, vec![1]);
+        assert_eq!(map.len(), 1);
+    }
+}
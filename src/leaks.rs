@@ -0,0 +1,100 @@
+//! Leak detection, counts the RCells holding a strong reference per type.
+//!
+//! ```
+//! use rcell::*;
+//!
+//! // print whatever is still retained when main() returns
+//! let _report = leaks::report_on_drop();
+//!
+//! let cell = RCell::new(1u32);
+//! assert!(leaks::report().iter().any(|(name, _)| *name == "u32"));
+//! ```
+
+use std::any::type_name;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+static RETAINED: Mutex<Option<HashMap<&'static str, usize>>> = Mutex::new(None);
+
+/// Accounts a state transition of a `RCell<T>`.
+pub(crate) fn transition<T: ?Sized>(was_strong: bool, is_strong: bool) {
+    if was_strong == is_strong {
+        return;
+    }
+    let mut lock = RETAINED.lock().unwrap_or_else(|poison| poison.into_inner());
+    let map = lock.get_or_insert_with(HashMap::new);
+    let count = map.entry(type_name::<T>()).or_default();
+    if is_strong {
+        *count += 1;
+    } else {
+        *count = count.saturating_sub(1);
+    }
+}
+
+/// Returns the type names and number of RCells which currently hold a strong reference,
+/// sorted by type name.
+pub fn report() -> Vec<(&'static str, usize)> {
+    let lock = RETAINED.lock().unwrap_or_else(|poison| poison.into_inner());
+    let mut report: Vec<_> = lock
+        .iter()
+        .flatten()
+        .filter(|(_, count)| **count > 0)
+        .map(|(name, count)| (*name, *count))
+        .collect();
+    report.sort_unstable();
+    report
+}
+
+/// Prints the `report()` to stderr.
+pub fn print_report() {
+    for (name, count) in report() {
+        eprintln!("rcell: {count} retained RCell<{name}>");
+    }
+}
+
+/// Guard which prints the `report()` to stderr when dropped, keep it alive in `main()` to get
+/// a report at program exit.
+#[derive(Debug)]
+#[must_use]
+pub struct ReportOnDrop;
+
+impl Drop for ReportOnDrop {
+    fn drop(&mut self) {
+        print_report();
+    }
+}
+
+/// Returns a guard which prints the `report()` when it gets dropped.
+pub fn report_on_drop() -> ReportOnDrop {
+    ReportOnDrop
+}
+
+#[cfg(test)]
+mod tests {
+    use super::report;
+    use crate::{RCell, Replace, Strong};
+
+    fn count<T>() -> usize {
+        report()
+            .into_iter()
+            .find(|(name, _)| *name == std::any::type_name::<T>())
+            .map_or(0, |(_, count)| count)
+    }
+
+    #[test]
+    fn track() {
+        struct Tracked;
+        let mut first = RCell::new(Tracked);
+        let second = RCell::from(first.request().unwrap());
+        assert_eq!(count::<Tracked>(), 2);
+        first.release();
+        assert_eq!(count::<Tracked>(), 1);
+        first.retain();
+        assert_eq!(count::<Tracked>(), 2);
+        drop(second);
+        first.remove();
+        assert_eq!(count::<Tracked>(), 0);
+        first.replace(Strong::new(Tracked));
+        assert_eq!(count::<Tracked>(), 1);
+    }
+}
@@ -22,135 +22,853 @@ pub use std::sync::{Arc as Strong, Weak};
 #[doc(hidden)]
 pub use std::rc::{Rc as Strong, Weak};
 
-/// A RCell holding either an `Strong<T>`, a `Weak<T>` or being `Empty`.
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+mod small;
+pub use small::SmallRCell;
+
+mod strong;
+pub use strong::StrongCell;
+
+#[cfg(feature = "timer")]
+mod timer;
+#[cfg(feature = "timer")]
+pub use timer::retain_for;
+
+mod retainer;
+pub use retainer::{EvictionListener, EvictionReason, PriorityRetainer, RetainerKey};
+
+#[cfg(feature = "pressure")]
+mod pressure;
+#[cfg(feature = "pressure")]
+pub use pressure::{PressureSource, PressureWatcher, Relieve};
+
+mod cost;
+pub use cost::CostOf;
+
+mod policy;
+pub use policy::{AccessInfo, MaxIdle, MinAccesses, PolicyCell, RetentionPolicy, TinyLfu};
+
+mod cache;
+pub use cache::{CacheEntry, CachePolicy, EntryInfo, Lfu, Lru, RCellCache, Usage};
+
+#[cfg(feature = "sync")]
+mod budget;
+#[cfg(feature = "sync")]
+pub use budget::{BudgetOrder, MemoryBudget};
+
+#[cfg(feature = "sync")]
+mod registry;
+#[cfg(feature = "sync")]
+pub use registry::{RegistryReader, RegistryScope, TypeRegistry};
+
+#[cfg(feature = "sync")]
+mod sharded;
+#[cfg(feature = "sync")]
+pub use sharded::{ShardedCacheEntry, ShardedRCellCache};
+
+mod graph;
+pub use graph::{CellGraph, GraphError};
+
+mod id;
+pub use id::{IdTable, RCellId};
+
+mod observers;
+pub use observers::Observers;
+
+mod byptr;
+pub use byptr::ByPtr;
+
+mod canonical;
+
+mod celled;
+pub use celled::RCelled;
+
+mod callback;
+pub use callback::{CallbackTarget, WeakCallback};
+
+mod asset;
+pub use asset::{Asset, AssetCache, ReloadHook};
+
+#[cfg(feature = "sync")]
+mod plugin;
+#[cfg(feature = "sync")]
+pub use plugin::PluginRegistry;
+
+mod handle;
+pub use handle::HandleTable;
+
+mod session;
+pub use session::SessionStore;
+
+mod weakkey;
+pub use weakkey::WeakKeyMap;
+
+mod double;
+pub use double::DoubleRCell;
+
+mod undo;
+pub use undo::UndoRCell;
+
+mod versioned;
+pub use versioned::VersionedRCell;
+
+mod counted;
+pub use counted::CountedRCell;
+
+mod asynccell;
+pub use asynccell::{AsyncLoader, AsyncRCell};
+
+mod healing;
+pub use healing::HealingRCell;
+
+mod hooks;
+pub use hooks::{HookedRCell, RCellHooks};
+
+mod any;
+pub use any::{AnyRCell, AnyValue, DynAny};
+
+mod option;
+pub use option::OptionExt;
+
+mod iter;
+pub use iter::{DrainDead, UpgradeExt, UpgradeIter};
+
+mod retainable;
+pub use retainable::{release_all, retain_all, Retainable};
+
+mod visit;
+pub use visit::{count_retained, release_reachable, retained_bytes, CellVisitor, VisitCells};
+
+mod guard;
+pub use guard::{ReleaseGuard, RetainGuard};
+
+mod interior;
+#[cfg(feature = "sync")]
+pub use interior::{RCellMutexGuard, RCellReadGuard, RCellWriteGuard};
+pub use interior::{RCellRef, RCellRefMut};
+
+#[cfg(feature = "debug-leaks")]
+pub mod leaks;
+
+#[cfg(feature = "debug-history")]
+pub mod history;
+
+#[cfg(feature = "testing")]
+pub mod testing;
+
+#[cfg(feature = "stats")]
+mod stats;
+#[cfg(feature = "stats")]
+pub use stats::{stats, Stats};
+
+/// Generates `release_all()` and `remove_all()` methods for structs with RCell members.
+#[cfg(feature = "derive")]
+pub use rcell_derive::Release;
+
+/// Generates `retain_all()` and `retain_all_failed()` methods for structs with RCell members.
+#[cfg(feature = "derive")]
+pub use rcell_derive::Retain;
+
+/// Generates `get_*()`, `set_*()` and `release_*()` accessors for `#[rcell]` fields.
+#[cfg(feature = "derive")]
+pub use rcell_derive::Accessors;
+
+/// Implements `VisitCells` for structs, visiting RCell members and `#[visit]` fields.
+#[cfg(feature = "derive")]
+pub use rcell_derive::VisitCells;
+
+/// Memoizes a function returning `Strong<T>` in weak cells keyed by its arguments.
+#[cfg(feature = "derive")]
+pub use rcell_derive::cached;
+
+#[cfg(feature = "derive")]
+#[doc(hidden)]
+pub mod memo;
+
+/// Constructs RCells.
+///
+/// * `rcell!(expr)` creates a strong RCell holding `expr`.
+/// * `rcell!{ Weak: expr }` creates a weak RCell from the `Strong<T>` expression `expr`.
+/// * `rcell![vec: a, b, c]` creates a `Vec` of strong RCells.
+/// * `rcell!{ k1 => v1, k2 => v2 }` creates a `HashMap` of strong RCells.
+///
+/// ```
+/// use rcell::*;
+///
+/// let strong = Strong::new(1);
+/// let cell = rcell!{ Weak: strong };
+/// assert!(!cell.retained());
+///
+/// let cells = rcell![vec: 1, 2, 3];
+/// assert_eq!(*cells[1].request().unwrap(), 2);
+///
+/// let map = rcell!{ "one" => 1, "two" => 2 };
+/// assert_eq!(*map["two"].request().unwrap(), 2);
+/// ```
+#[macro_export]
+macro_rules! rcell {
+    (Weak: $strong:expr) => {
+        $crate::RCell::from($crate::Strong::downgrade(&$strong))
+    };
+    (vec: $($value:expr),* $(,)?) => {
+        ::std::vec![$($crate::RCell::new($value)),*]
+    };
+    ($($key:expr => $value:expr),+ $(,)?) => {
+        ::std::collections::HashMap::from([$(($key, $crate::RCell::new($value))),+])
+    };
+    ($value:expr) => {
+        $crate::RCell::new($value)
+    };
+}
+
+/// Creates a weak RCell from a `Strong<T>`, `weak!(a, b)` creates a `Vec` of weak RCells.
+///
+/// ```
+/// use rcell::*;
+///
+/// let strong = Strong::new("foo");
+/// let cell = weak!(strong);
+/// assert!(!cell.retained());
+/// assert_eq!(*cell.request().unwrap(), "foo");
+/// ```
+#[macro_export]
+macro_rules! weak {
+    ($strong:expr) => {
+        $crate::RCell::from($crate::Strong::downgrade(&$strong))
+    };
+    ($($strong:expr),+ $(,)?) => {
+        ::std::vec![$($crate::RCell::from($crate::Strong::downgrade(&$strong))),+]
+    };
+}
+
+/// A RCell holding either an `Strong<T>`, a `Weak<T>` or being `Empty`. The representation is
+/// opaque, use `state()` to inspect what the cell contains.
+pub struct RCell<T: ?Sized> {
+    inner: Inner<T>,
+    #[cfg(feature = "debug-history")]
+    history: history::History,
+}
+
+// Not packed into a single tagged word: `T: ?Sized` makes the pointers fat for trait
+// objects and slices, leaving no spare bits, and choosing a different representation for
+// sized `T` needs specialization. For sized `T` this takes two words.
 #[derive(Debug)]
-pub enum RCell<T> {
-    /// Strong reference
+enum Inner<T: ?Sized> {
     Strong(Strong<T>),
-    /// Weak reference
     Weak(Weak<T>),
+    Empty,
+}
+
+impl<T: ?Sized> Clone for Inner<T> {
+    fn clone(&self) -> Self {
+        match self {
+            Inner::Strong(strong) => Inner::Strong(strong.clone()),
+            Inner::Weak(weak) => Inner::Weak(weak.clone()),
+            Inner::Empty => Inner::Empty,
+        }
+    }
+}
+
+impl<T: ?Sized> Inner<T> {
+    #[cfg(feature = "debug-history")]
+    fn kind(&self) -> history::Kind {
+        match self {
+            Inner::Strong(_) => history::Kind::Strong,
+            Inner::Weak(_) => history::Kind::Weak,
+            Inner::Empty => history::Kind::Empty,
+        }
+    }
+}
+
+/// Operations changing the state of a RCell, as recorded by the **debug-history** feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// The RCell was constructed
+    New,
+    /// `retain()` upgraded the RCell
+    Retain,
+    /// `release()` downgraded the RCell
+    Release,
+    /// `remove()` emptied the RCell
+    Remove,
+    /// `replace()` stored a new reference
+    Replace,
+}
+
+/// A view on the content of a RCell as returned by `RCell::state()`.
+#[derive(Debug)]
+pub enum State<'a, T: ?Sized> {
+    /// Strong reference
+    Strong(&'a Strong<T>),
+    /// Weak reference
+    Weak(&'a Weak<T>),
+    /// Empty cell
+    Empty,
+}
+
+/// The raw pointers of a RCell as returned by `RCell::into_raw()`. Each pointer owns one
+/// strong respective weak reference count until it is turned back into a RCell by
+/// `RCell::from_raw()`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum RawRCell<T: ?Sized> {
+    /// Pointer from `Strong::into_raw()`
+    Strong(*const T),
+    /// Pointer from `Weak::into_raw()`
+    Weak(*const T),
     /// Empty cell
     Empty,
 }
 
 impl<T> RCell<T> {
-    /// Creates a new strong (Strong<T>) RCell from the supplied value.
+    /// Creates a new strong (`Strong<T>`) RCell from the supplied value.
     pub fn new(value: T) -> Self {
-        RCell::Strong(Strong::new(value))
+        RCell::from_inner(Inner::Strong(Strong::new(value)))
+    }
+
+    /// Creates a new strong RCell from the value built by `f`, passes its error through.
+    pub fn new_with<E>(f: impl FnOnce() -> Result<T, E>) -> Result<Self, E> {
+        f().map(RCell::new)
+    }
+
+    /// Creates a new strong RCell from the value built by `f` which gets a weak reference
+    /// to the value under construction, see `Strong::new_cyclic()`.
+    pub fn new_cyclic(f: impl FnOnce(&Weak<T>) -> T) -> Self {
+        RCell::from_inner(Inner::Strong(Strong::new_cyclic(f)))
+    }
+
+    /// Returns a pointer to the value without touching the reference counts, for identity
+    /// comparisons. It must not be dereferenced unless the value is known to be alive. Empty
+    /// cells return a null pointer.
+    pub fn as_ptr(&self) -> *const T {
+        match &self.inner {
+            Inner::Strong(strong) => Strong::as_ptr(strong),
+            Inner::Weak(weak) => Weak::as_ptr(weak),
+            Inner::Empty => std::ptr::null(),
+        }
+    }
+
+    /// Computes a new value from the current one and stores it as a fresh `Strong<T>`. Returns
+    /// the new strong reference or `None` when the value was not alive, the cell is left
+    /// unchanged then.
+    pub fn update(&mut self, f: impl FnOnce(&T) -> T) -> Option<Strong<T>> {
+        let strong = Strong::new(f(&*self.request()?));
+        self.set(Operation::Replace, Inner::Strong(strong.clone()));
+        Some(strong)
+    }
+}
+
+impl<T: ?Sized> RCell<T> {
+    fn from_inner(inner: Inner<T>) -> Self {
+        let mut rcell = RCell::empty();
+        rcell.set(Operation::New, inner);
+        rcell
+    }
+
+    /// Replaces the content of this RCell, all state transitions go through here.
+    fn set(&mut self, _operation: Operation, inner: Inner<T>) {
+        #[cfg(feature = "debug-leaks")]
+        leaks::transition::<T>(self.retained(), matches!(inner, Inner::Strong(_)));
+        #[cfg(feature = "debug-history")]
+        self.history
+            .record(_operation, self.inner.kind(), inner.kind());
+        let _ = mem::replace(&mut self.inner, inner);
+    }
+
+    /// Creates an RCell that doesn't hold any reference. Unlike `default()` this can be used
+    /// in const contexts.
+    pub const fn empty() -> Self {
+        RCell {
+            inner: Inner::Empty,
+            #[cfg(feature = "debug-history")]
+            history: history::History::new(),
+        }
+    }
+
+    /// Returns a view on the content of this RCell for matching on it.
+    pub fn state(&self) -> State<'_, T> {
+        match &self.inner {
+            Inner::Strong(strong) => State::Strong(strong),
+            Inner::Weak(weak) => State::Weak(weak),
+            Inner::Empty => State::Empty,
+        }
     }
 
     /// Returns 'true' when this RCell contains a `Strong<T>`.
     pub fn retained(&self) -> bool {
-        matches!(*self, RCell::Strong(_))
+        matches!(self.inner, Inner::Strong(_))
     }
 
     /// Returns the number of strong references holding an object alive. The returned strong
     /// count is informal only, the result may be approximate and has race conditions when
     /// other threads modify the reference count concurrently.
     pub fn refcount(&self) -> usize {
-        match self {
-            RCell::Strong(arc) => Strong::strong_count(arc),
-            RCell::Weak(weak) => weak.strong_count(),
-            RCell::Empty => 0,
+        match &self.inner {
+            Inner::Strong(arc) => Strong::strong_count(arc),
+            Inner::Weak(weak) => weak.strong_count(),
+            Inner::Empty => 0,
         }
     }
 
-    /// Tries to upgrade this RCell from Weak<T> to Strong<T>. This means that as long the RCell
+    /// Returns the strong and weak reference counts of the value. Like `refcount()` this is
+    /// informal only, each count is read atomically but other threads may change them in
+    /// between.
+    pub fn refcounts(&self) -> Counts {
+        match &self.inner {
+            Inner::Strong(strong) => Counts {
+                strong: Strong::strong_count(strong),
+                weak: Strong::weak_count(strong),
+            },
+            Inner::Weak(weak) => Counts {
+                strong: weak.strong_count(),
+                weak: weak.weak_count(),
+            },
+            Inner::Empty => Counts::default(),
+        }
+    }
+
+    /// Returns 'true' when this RCell holds the only strong reference to the value.
+    pub fn is_unique(&self) -> bool {
+        matches!(&self.inner, Inner::Strong(strong) if Strong::strong_count(strong) == 1)
+    }
+
+    /// Tries to upgrade this RCell from `Weak<T>` to `Strong<T>`. This means that as long the RCell
     /// is not dropped the associated data won't be either. When successful it returns
-    /// Some<Strong<T>> containing the value, otherwise None is returned on failure.
+    /// `Some<Strong<T>>` containing the value, otherwise None is returned on failure.
     pub fn retain(&mut self) -> Option<Strong<T>> {
-        match self {
-            RCell::Strong(strong) => Some(strong.clone()),
-            RCell::Weak(weak) => {
-                if let Some(strong) = weak.upgrade() {
-                    let _ = mem::replace(self, RCell::Strong(strong.clone()));
+        let strong = match &self.inner {
+            Inner::Strong(strong) => Some(strong.clone()),
+            Inner::Weak(weak) => {
+                if let Some(strong) = upgrade(weak) {
+                    self.set(Operation::Retain, Inner::Strong(strong.clone()));
                     Some(strong)
                 } else {
                     None
                 }
             }
-            RCell::Empty => None,
+            Inner::Empty => None,
+        };
+        #[cfg(feature = "stats")]
+        if strong.is_some() {
+            stats::bump(&stats::RETAINS);
+        }
+        strong
+    }
+
+    /// Like `retain()` but tells why retaining failed. `RetainError::WasEmpty` when the cell
+    /// holds no reference, `RetainError::ValueDropped` when the value is gone.
+    pub fn try_retain(&mut self) -> Result<Strong<T>, RetainError> {
+        match &self.inner {
+            Inner::Empty => Err(RetainError::WasEmpty),
+            _ => self.retain().ok_or(RetainError::ValueDropped),
         }
     }
 
     /// Downgrades the RCell, any associated value may become dropped when no other references
     /// exist. When no strong reference left remaining this cell becomes Empty.
     pub fn release(&mut self) {
-        if let Some(weak) = match self {
-            RCell::Strong(strong) => Some(Strong::downgrade(strong)),
-            RCell::Weak(weak) => Some(weak.clone()),
-            RCell::Empty => None,
+        if let Some(weak) = match &self.inner {
+            Inner::Strong(strong) => {
+                #[cfg(feature = "stats")]
+                stats::bump(&stats::RELEASES);
+                Some(Strong::downgrade(strong))
+            }
+            Inner::Weak(weak) => Some(weak.clone()),
+            Inner::Empty => None,
         } {
             if weak.strong_count() > 0 {
-                let _ = mem::replace(self, RCell::Weak(weak));
+                self.set(Operation::Release, Inner::Weak(weak));
             } else {
-                let _ = mem::replace(self, RCell::Empty);
+                self.set(Operation::Release, Inner::Empty);
             }
         }
     }
 
+    /// Releases the RCell when it holds the only strong reference to the value, that is when
+    /// nobody else uses the value anymore. Returns 'true' when the cell was released. This is
+    /// the canonical cache eviction trigger, see also `release_unique()`.
+    pub fn release_if_unique(&mut self) -> bool {
+        let unique = self.is_unique();
+        if unique {
+            self.release();
+        }
+        unique
+    }
+
+    /// Hands out the strong reference while demoting this RCell to Weak in one operation, the
+    /// caller takes ownership and the cell only keeps tracking the value. Returns `None` when
+    /// the value is not alive.
+    pub fn split(&mut self) -> Option<Strong<T>> {
+        let strong = self.request()?;
+        if self.retained() {
+            #[cfg(feature = "stats")]
+            stats::bump(&stats::RELEASES);
+            self.set(Operation::Release, Inner::Weak(Strong::downgrade(&strong)));
+        }
+        Some(strong)
+    }
+
     /// Removes the reference to the value. The rationale for this function is to release
     /// *any* resource associated with a RCell (potentially member of a struct that lives
     /// longer) in case one knows that it will never be upgraded again.
     pub fn remove(&mut self) {
-        let _ = mem::replace(self, RCell::Empty);
+        #[cfg(feature = "stats")]
+        stats::bump(&stats::REMOVES);
+        self.set(Operation::Remove, Inner::Empty);
     }
 
     /// Tries to get an `Strong<T>` from the RCell. This may fail if the RCell was Weak and all
     /// other strong references became dropped.
     pub fn request(&self) -> Option<Strong<T>> {
+        match &self.inner {
+            Inner::Strong(arc) => Some(arc.clone()),
+            Inner::Weak(weak) => upgrade(weak),
+            Inner::Empty => None,
+        }
+    }
+
+    /// Requests the values of this and the `other` RCell together. Returns `None` unless both
+    /// are alive, no partial result is handed out.
+    pub fn zip<U: ?Sized>(&self, other: &RCell<U>) -> Option<(Strong<T>, Strong<U>)> {
+        Some((self.request()?, other.request()?))
+    }
+
+    /// Combines two RCells into one, keeping the stronger handle: a `Strong<T>` beats a live
+    /// `Weak<T>` which beats a dead or no reference. On a tie self is kept, the other cell is
+    /// dropped.
+    pub fn merge(self, other: RCell<T>) -> RCell<T> {
+        if other.rank() > self.rank() {
+            other
+        } else {
+            self
+        }
+    }
+
+    /// Consumes the cell and leaks its value, which then lives for the rest of the program.
+    /// Only a cell holding the only strong reference can be leaked, see `is_unique()`, other
+    /// owners must not expect the value to go away when they drop it. Returns `None` and
+    /// drops the cell otherwise.
+    pub fn leak(self) -> Option<&'static T>
+    where
+        T: 'static,
+    {
+        if !self.is_unique() {
+            return None;
+        }
+        let strong = self.request()?;
+        drop(self);
+        // SAFETY: the strong reference count taken by into_raw() is never released, the value
+        // stays alive for the rest of the program.
+        Some(unsafe { &*Strong::into_raw(strong) })
+    }
+
+    /// Consumes the cell and returns its reference as raw pointer, for storing cells in type
+    /// erased storage or passing them through FFI. The reference counts are kept, the cell
+    /// has to be reconstructed with `from_raw()` to release them.
+    pub fn into_raw(self) -> RawRCell<T> {
+        match &self.inner {
+            Inner::Strong(strong) => RawRCell::Strong(Strong::into_raw(strong.clone())),
+            Inner::Weak(weak) => RawRCell::Weak(Weak::into_raw(weak.clone())),
+            Inner::Empty => RawRCell::Empty,
+        }
+    }
+
+    /// Reconstructs a cell from the pointers returned by `into_raw()`.
+    ///
+    /// # Safety
+    ///
+    /// The pointers must come from `into_raw()` of a `RCell<T>` and each may be passed to
+    /// `from_raw()` only once, see `Strong::from_raw()` and `Weak::from_raw()`.
+    pub unsafe fn from_raw(raw: RawRCell<T>) -> Self {
+        RCell::from_inner(match raw {
+            RawRCell::Strong(ptr) => Inner::Strong(Strong::from_raw(ptr)),
+            RawRCell::Weak(ptr) => Inner::Weak(Weak::from_raw(ptr)),
+            RawRCell::Empty => Inner::Empty,
+        })
+    }
+
+    /// Returns the address of the allocation this cell refers to, without touching the
+    /// reference counts. A weak reference keeps reporting the address after its value got
+    /// dropped. Empty cells return 0.
+    pub fn addr(&self) -> usize {
+        match &self.inner {
+            Inner::Strong(strong) => Strong::as_ptr(strong) as *const () as usize,
+            Inner::Weak(weak) => Weak::as_ptr(weak) as *const () as usize,
+            Inner::Empty => 0,
+        }
+    }
+
+    fn rank(&self) -> u8 {
+        match &self.inner {
+            Inner::Strong(_) => 2,
+            Inner::Weak(weak) if weak.strong_count() > 0 => 1,
+            _ => 0,
+        }
+    }
+
+    /// Calls `f` with the value when it is alive and returns self for chaining.
+    pub fn inspect(&self, f: impl FnOnce(&T)) -> &Self {
+        if let Some(strong) = self.request() {
+            f(&strong);
+        }
+        self
+    }
+
+    /// Like `request()` but refuses to create more than `cap` outstanding strong references.
+    /// Returns `Ok(None)` when the value is not alive. The count is checked after the new
+    /// reference got created, when other threads create references concurrently some of
+    /// them may fail even if the cap would not be exceeded in the end.
+    pub fn request_capped(&self, cap: usize) -> Result<Option<Strong<T>>, CapExceeded> {
+        match self.request() {
+            Some(strong) if Strong::strong_count(&strong) > cap => Err(CapExceeded { cap }),
+            other => Ok(other),
+        }
+    }
+
+    /// Like `retain()` but refuses to create more than `cap` outstanding strong references,
+    /// the strong reference in this RCell counts as one of them. The cell is left unchanged
+    /// on failure.
+    pub fn retain_capped(&mut self, cap: usize) -> Result<Option<Strong<T>>, CapExceeded> {
+        // a released cell adds its own clone of the returned reference
+        let own = usize::from(!self.retained());
+        let strong = match self.request() {
+            Some(strong) if Strong::strong_count(&strong) + own > cap => {
+                return Err(CapExceeded { cap })
+            }
+            other => other,
+        };
+        if let Some(strong) = &strong {
+            if own == 1 {
+                self.set(Operation::Retain, Inner::Strong(strong.clone()));
+            }
+        }
+        Ok(strong)
+    }
+}
+
+/// Upgrades a weak reference, all upgrades of RCells go through here.
+fn upgrade<T: ?Sized>(weak: &Weak<T>) -> Option<Strong<T>> {
+    let strong = weak.upgrade();
+    #[cfg(feature = "stats")]
+    stats::bump(if strong.is_some() {
+        &stats::UPGRADES
+    } else {
+        &stats::FAILED_UPGRADES
+    });
+    strong
+}
+
+/// Sweeps over a collection of RCells and calls `release_if_unique()` on each of them. Returns
+/// the number of cells released.
+pub fn release_unique<'a, T: ?Sized + 'a>(
+    cells: impl IntoIterator<Item = &'a mut RCell<T>>,
+) -> usize {
+    cells
+        .into_iter()
+        .map(|cell| usize::from(cell.release_if_unique()))
+        .sum()
+}
+
+/// Strong and weak reference counts as returned by `RCell::refcounts()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Counts {
+    /// Number of strong references.
+    pub strong: usize,
+    /// Number of weak references.
+    pub weak: usize,
+}
+
+/// Error returned by `RCell::try_retain()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetainError {
+    /// The cell did not hold any reference.
+    WasEmpty,
+    /// The cell held a weak reference but the value got dropped.
+    ValueDropped,
+}
+
+impl std::fmt::Display for RetainError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            RCell::Strong(arc) => Some(arc.clone()),
-            RCell::Weak(weak) => weak.upgrade(),
-            RCell::Empty => None,
+            RetainError::WasEmpty => write!(f, "RCell is empty"),
+            RetainError::ValueDropped => write!(f, "value was dropped"),
         }
     }
 }
 
+impl std::error::Error for RetainError {}
+
+/// Error returned by `RCell::request_capped()` and `RCell::retain_capped()` when the cap on
+/// outstanding strong references would be exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapExceeded {
+    /// The cap that was hit.
+    pub cap: usize,
+}
+
+impl std::fmt::Display for CapExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "more than {} strong references", self.cap)
+    }
+}
+
+impl std::error::Error for CapExceeded {}
+
 /// Helper Trait for replacing the content of a RCell with something new.
 pub trait Replace<T> {
     /// Replaces the contained value in self with T.
     fn replace(&mut self, new: T);
 }
 
-impl<T> Replace<Strong<T>> for RCell<T> {
+impl<T: ?Sized> Replace<Strong<T>> for RCell<T> {
     /// Replaces the RCell with the supplied `Strong<T>`. The old entry becomes dropped.
     fn replace(&mut self, strong: Strong<T>) {
-        let _ = mem::replace(self, RCell::Strong(strong));
+        self.set(Operation::Replace, Inner::Strong(strong));
     }
 }
 
-impl<T> Replace<Weak<T>> for RCell<T> {
+impl<T: ?Sized> Replace<Weak<T>> for RCell<T> {
     /// Replaces the RCell with the supplied `Weak<T>`. The old entry becomes dropped.
     fn replace(&mut self, weak: Weak<T>) {
-        let _ = mem::replace(self, RCell::Weak(weak));
+        self.set(Operation::Replace, Inner::Weak(weak));
     }
 }
 
-impl<T> From<Strong<T>> for RCell<T> {
+impl<T: ?Sized> From<Strong<T>> for RCell<T> {
     /// Creates a new strong RCell with the supplied `Strong<T>`.
     fn from(strong: Strong<T>) -> Self {
-        RCell::Strong(strong)
+        RCell::from_inner(Inner::Strong(strong))
     }
 }
 
-impl<T> From<Weak<T>> for RCell<T> {
+impl<T: ?Sized> From<Weak<T>> for RCell<T> {
     /// Creates a new weak RCell with the supplied `Weak<T>`.
     fn from(weak: Weak<T>) -> Self {
-        RCell::Weak(weak)
+        RCell::from_inner(Inner::Weak(weak))
+    }
+}
+
+impl<T: ?Sized> RCell<T> {
+    fn state_name(&self) -> &'static str {
+        match &self.inner {
+            Inner::Strong(_) => "Strong",
+            Inner::Weak(_) => "Weak",
+            Inner::Empty => "Empty",
+        }
+    }
+
+    fn debug_fields<'a, 'b>(
+        &self,
+        f: &'a mut std::fmt::Formatter<'b>,
+    ) -> std::fmt::DebugStruct<'a, 'b> {
+        let counts = self.refcounts();
+        let mut debug = f.debug_struct("RCell");
+        debug
+            .field("state", &format_args!("{}", self.state_name()))
+            .field("strong", &counts.strong)
+            .field("weak", &counts.weak);
+        debug
+    }
+}
+
+impl<T: ?Sized> std::fmt::Debug for RCell<T> {
+    /// Prints the state and the reference counts, use `debug_with_value()` to include the
+    /// value.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.debug_fields(f).finish()
+    }
+}
+
+impl<T: ?Sized + std::fmt::Debug> RCell<T> {
+    /// Returns a wrapper whose `Debug` output includes the value when it is alive.
+    pub fn debug_with_value(&self) -> DebugWithValue<'_, T> {
+        DebugWithValue(self)
     }
 }
 
-impl<T> Default for RCell<T> {
+/// `Debug` wrapper returned by `RCell::debug_with_value()`.
+pub struct DebugWithValue<'a, T: ?Sized>(&'a RCell<T>);
+
+impl<T: ?Sized + std::fmt::Debug> std::fmt::Debug for DebugWithValue<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut debug = self.0.debug_fields(f);
+        if let Some(strong) = self.0.request() {
+            debug.field("value", &&*strong);
+        }
+        debug.finish()
+    }
+}
+
+#[cfg(feature = "debug-leaks")]
+impl<T: ?Sized> Drop for RCell<T> {
+    fn drop(&mut self) {
+        leaks::transition::<T>(self.retained(), false);
+    }
+}
+
+impl<T: ?Sized> Clone for RCell<T> {
+    /// Creates a RCell holding the same kind of reference to the same value.
+    fn clone(&self) -> Self {
+        RCell::from_inner(self.inner.clone())
+    }
+
+    /// Makes this RCell hold the same kind of reference as `source`. Nothing is changed when
+    /// both already hold the same kind of reference to the same value, saving the reference
+    /// count updates.
+    fn clone_from(&mut self, source: &Self) {
+        let same = match (&self.inner, &source.inner) {
+            (Inner::Strong(a), Inner::Strong(b)) => Strong::ptr_eq(a, b),
+            (Inner::Weak(a), Inner::Weak(b)) => Weak::ptr_eq(a, b),
+            (Inner::Empty, Inner::Empty) => true,
+            _ => false,
+        };
+        if !same {
+            self.set(Operation::Replace, source.inner.clone());
+        }
+    }
+}
+
+impl<T: ?Sized> Default for RCell<T> {
     /// Creates an RCell that doesn't hold any reference.
     fn default() -> Self {
-        RCell::Empty
+        RCell::empty()
+    }
+}
+
+impl<T> RCell<[T]> {
+    /// Creates a new strong RCell holding the elements of `vec` as `Strong<[T]>`.
+    pub fn from_vec(vec: Vec<T>) -> Self {
+        RCell::from(Strong::<[T]>::from(vec))
+    }
+}
+
+impl<T> FromIterator<T> for RCell<[T]> {
+    /// Creates a new strong RCell holding the collected elements as `Strong<[T]>`.
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        RCell::from(iter.into_iter().collect::<Strong<[T]>>())
+    }
+}
+
+impl From<&str> for RCell<str> {
+    /// Creates a new strong RCell holding a copy of `string` as `Strong<str>`.
+    fn from(string: &str) -> Self {
+        RCell::from(Strong::<str>::from(string))
+    }
+}
+
+impl From<String> for RCell<str> {
+    /// Creates a new strong RCell holding `string` as `Strong<str>`.
+    fn from(string: String) -> Self {
+        RCell::from(Strong::<str>::from(string))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{RCell, Strong, Replace};
+    use crate::{
+        release_unique, CapExceeded, Counts, RCell, RawRCell, Replace, RetainError, State, Strong,
+    };
 
     #[test]
     fn smoke() {
@@ -158,6 +876,14 @@ mod tests {
         assert!(rcell.retained());
     }
 
+    #[test]
+    #[cfg(not(feature = "debug-history"))]
+    fn size() {
+        use std::mem::size_of;
+        assert_eq!(size_of::<RCell<u64>>(), 2 * size_of::<usize>());
+        assert_eq!(size_of::<Option<RCell<u64>>>(), 2 * size_of::<usize>());
+    }
+
     #[test]
     fn new() {
         let mut rcell = RCell::new("foobar");
@@ -230,4 +956,310 @@ mod tests {
         rcell.remove();
         assert_eq!(rcell.request(), None);
     }
+
+    #[test]
+    fn macros() {
+        let rcell = rcell!("foobar");
+        assert!(rcell.retained());
+
+        let strong = Strong::new("foobar");
+        let rcell = rcell! { Weak: strong };
+        assert!(!rcell.retained());
+        let rcell = weak!(strong);
+        assert_eq!(*rcell.request().unwrap(), "foobar");
+
+        let cells = weak!(strong, strong);
+        assert_eq!(cells.len(), 2);
+        assert!(!cells[0].retained());
+
+        let cells = rcell![vec: 1, 2];
+        assert_eq!(*cells[1].request().unwrap(), 2);
+        let cells = rcell![vec: 1];
+        assert_eq!(cells.len(), 1);
+
+        let map = rcell! { 1 => "one", 2 => "two" };
+        assert_eq!(*map[&1].request().unwrap(), "one");
+    }
+
+    #[test]
+    fn state() {
+        let strong = Strong::new("foobar");
+        let mut rcell = RCell::from(strong.clone());
+        assert!(matches!(rcell.state(), State::Strong(s) if Strong::ptr_eq(s, &strong)));
+        rcell.release();
+        assert!(matches!(rcell.state(), State::Weak(_)));
+        rcell.remove();
+        assert!(matches!(rcell.state(), State::Empty));
+    }
+
+    #[test]
+    fn const_empty() {
+        const EMPTY: RCell<u8> = RCell::empty();
+        assert!(matches!(EMPTY.state(), State::Empty));
+    }
+
+    #[test]
+    fn capped() {
+        let rcell = RCell::new("foobar");
+        let first = rcell.request_capped(2).unwrap().unwrap();
+        assert_eq!(rcell.request_capped(2), Err(CapExceeded { cap: 2 }));
+        drop(first);
+        assert_eq!(*rcell.request_capped(2).unwrap().unwrap(), "foobar");
+
+        let strong = Strong::new("foobar");
+        let mut rcell = RCell::from(Strong::downgrade(&strong));
+        assert_eq!(rcell.retain_capped(1), Err(CapExceeded { cap: 1 }));
+        assert!(!rcell.retained());
+        assert!(rcell.retain_capped(3).unwrap().is_some());
+        assert!(rcell.retained());
+        assert_eq!(rcell.refcount(), 2);
+
+        // the outside reference, the returned one and the one stored in the cell
+        let strong = Strong::new("foobar");
+        let mut rcell = RCell::from(Strong::downgrade(&strong));
+        assert_eq!(rcell.retain_capped(2), Err(CapExceeded { cap: 2 }));
+        assert!(!rcell.retained());
+        assert_eq!(Strong::strong_count(&strong), 1);
+
+        let mut rcell = RCell::<u8>::default();
+        assert_eq!(rcell.retain_capped(1), Ok(None));
+    }
+
+    #[test]
+    fn release_if_unique() {
+        let mut rcell = RCell::new("foobar");
+        let strong = rcell.request().unwrap();
+        assert!(!rcell.release_if_unique());
+        assert!(rcell.retained());
+        drop(strong);
+        assert!(rcell.release_if_unique());
+        assert_eq!(rcell.request(), None);
+    }
+
+    #[test]
+    fn sweep_unique() {
+        let mut cells = vec![RCell::new(1), RCell::new(2), RCell::new(3)];
+        let keep = cells[1].request().unwrap();
+        assert_eq!(release_unique(&mut cells), 2);
+        assert!(cells[1].retained());
+        assert_eq!(cells[0].request(), None);
+        drop(keep);
+    }
+
+    #[test]
+    fn update() {
+        let mut rcell = RCell::new(1);
+        let old = rcell.request().unwrap();
+        assert_eq!(*rcell.update(|value| value + 1).unwrap(), 2);
+        assert!(rcell.retained());
+        assert_eq!(*rcell.request().unwrap(), 2);
+        assert_eq!(*old, 1);
+
+        rcell.remove();
+        assert_eq!(rcell.update(|value| value + 1), None);
+        assert_eq!(rcell.request(), None);
+    }
+
+    #[test]
+    fn inspect() {
+        let mut seen = Vec::new();
+        let mut rcell = RCell::new(1);
+        assert!(rcell.inspect(|value| seen.push(*value)).retained());
+        rcell.release();
+        rcell.inspect(|value| seen.push(*value));
+        assert_eq!(seen, vec![1]);
+    }
+
+    #[test]
+    fn zip() {
+        let first = RCell::new(1);
+        let mut second = RCell::new("two");
+        let (one, two) = first.zip(&second).unwrap();
+        assert_eq!((*one, *two), (1, "two"));
+        drop(two);
+        second.release();
+        assert_eq!(first.zip(&second), None);
+    }
+
+    #[test]
+    fn slice() {
+        let mut rcell = RCell::from_vec(vec![1, 2, 3]);
+        assert!(rcell.retained());
+        assert_eq!(*rcell.request().unwrap(), [1, 2, 3]);
+        rcell.release();
+        assert_eq!(rcell.request(), None);
+
+        let rcell: RCell<[u8]> = (1..=3).collect();
+        assert_eq!(rcell.request().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn str() {
+        let mut rcell = RCell::<str>::from("foobar");
+        assert_eq!(&*rcell.request().unwrap(), "foobar");
+        rcell.release();
+        assert_eq!(rcell.request(), None);
+
+        let rcell = RCell::<str>::from(String::from("foobar"));
+        assert_eq!(&*rcell.request().unwrap(), "foobar");
+    }
+
+    #[test]
+    fn try_retain() {
+        let mut rcell = RCell::<u8>::default();
+        assert_eq!(rcell.try_retain(), Err(RetainError::WasEmpty));
+
+        let strong = Strong::new(1);
+        rcell.replace(Strong::downgrade(&strong));
+        assert_eq!(*rcell.try_retain().unwrap(), 1);
+        assert!(rcell.retained());
+
+        drop(strong);
+        rcell.release();
+        assert_eq!(rcell.try_retain(), Err(RetainError::ValueDropped));
+    }
+
+    #[test]
+    fn refcounts() {
+        let mut rcell = RCell::new(1);
+        assert_eq!(rcell.refcounts(), Counts { strong: 1, weak: 0 });
+        assert!(rcell.is_unique());
+        let strong = rcell.request().unwrap();
+        let _weak = Strong::downgrade(&strong);
+        assert_eq!(rcell.refcounts(), Counts { strong: 2, weak: 1 });
+        assert!(!rcell.is_unique());
+        rcell.release();
+        assert_eq!(rcell.refcounts(), Counts { strong: 1, weak: 2 });
+        assert!(!rcell.is_unique());
+        rcell.remove();
+        assert_eq!(rcell.refcounts(), Counts::default());
+    }
+
+    #[test]
+    fn debug() {
+        struct NoDebug;
+        let rcell = RCell::new(NoDebug);
+        assert_eq!(
+            format!("{rcell:?}"),
+            "RCell { state: Strong, strong: 1, weak: 0 }"
+        );
+
+        let mut rcell = RCell::new(1);
+        assert_eq!(
+            format!("{:?}", rcell.debug_with_value()),
+            "RCell { state: Strong, strong: 1, weak: 0, value: 1 }"
+        );
+        let strong = rcell.request().unwrap();
+        rcell.release();
+        assert_eq!(
+            format!("{:?}", rcell.debug_with_value()),
+            "RCell { state: Weak, strong: 1, weak: 1, value: 1 }"
+        );
+        drop(strong);
+        assert_eq!(
+            format!("{:?}", rcell.debug_with_value()),
+            "RCell { state: Weak, strong: 0, weak: 0 }"
+        );
+    }
+
+    #[test]
+    fn merge() {
+        let strong = Strong::new(1);
+        let weak = RCell::from(Strong::downgrade(&strong));
+        let merged = RCell::empty().merge(weak);
+        assert!(matches!(merged.state(), State::Weak(_)));
+        let merged = merged.merge(RCell::new(2));
+        assert_eq!(*merged.request().unwrap(), 2);
+        let merged = merged.merge(RCell::new(3));
+        assert_eq!(*merged.request().unwrap(), 2);
+
+        let dead = RCell::from(Strong::downgrade(&Strong::new(4)));
+        let merged = dead.merge(RCell::from(Strong::downgrade(&strong)));
+        assert_eq!(merged.request(), Some(strong));
+    }
+
+    #[test]
+    fn clone() {
+        let rcell = RCell::new(1);
+        let mut copy = rcell.clone();
+        assert_eq!(rcell.refcount(), 2);
+        copy.clone_from(&rcell);
+        assert_eq!(rcell.refcount(), 2);
+
+        let mut weak = RCell::empty();
+        copy.release();
+        weak.clone_from(&copy);
+        assert!(!weak.retained());
+        assert_eq!(weak.request(), rcell.request());
+        weak.clone_from(&RCell::empty());
+        assert!(matches!(weak.state(), State::Empty));
+        assert_eq!(rcell.refcounts(), Counts { strong: 1, weak: 1 });
+    }
+
+    #[test]
+    fn new_with() {
+        let rcell = RCell::new_with(|| "1".parse::<u8>()).unwrap();
+        assert_eq!(*rcell.request().unwrap(), 1);
+        assert!(RCell::new_with(|| "x".parse::<u8>()).is_err());
+    }
+
+    #[test]
+    fn split() {
+        let mut rcell = RCell::new(1);
+        let strong = rcell.split().unwrap();
+        assert!(!rcell.retained());
+        assert_eq!(rcell.refcount(), 1);
+        assert_eq!(rcell.split(), Some(strong.clone()));
+        drop(strong);
+        assert_eq!(rcell.split(), None);
+        assert_eq!(RCell::<u8>::empty().split(), None);
+    }
+
+    #[test]
+    fn leak() {
+        let leaked: &'static String = RCell::new(String::from("forever")).leak().unwrap();
+        assert_eq!(leaked, "forever");
+
+        let strong = Strong::new(String::from("shared"));
+        assert_eq!(RCell::from(strong.clone()).leak(), None);
+        assert_eq!(RCell::from(Strong::downgrade(&strong)).leak(), None);
+        assert_eq!(Strong::strong_count(&strong), 1);
+        assert_eq!(RCell::<u8>::empty().leak(), None);
+    }
+
+    #[test]
+    fn raw() {
+        let strong = Strong::new(1);
+        let cells = [
+            RCell::from(strong.clone()),
+            RCell::from(Strong::downgrade(&strong)),
+            RCell::empty(),
+        ];
+        let raw: Vec<_> = cells.into_iter().map(RCell::into_raw).collect();
+        assert_eq!(Strong::strong_count(&strong), 2);
+        assert_eq!(raw[0], RawRCell::Strong(Strong::as_ptr(&strong)));
+        let cells: Vec<_> = raw
+            .into_iter()
+            .map(|raw| unsafe { RCell::from_raw(raw) })
+            .collect();
+        assert!(cells[0].retained());
+        assert_eq!(cells[1].request(), Some(strong.clone()));
+        assert_eq!(cells[2].request(), None);
+        drop(cells);
+        assert_eq!(Strong::strong_count(&strong), 1);
+        assert_eq!(Strong::weak_count(&strong), 0);
+    }
+
+    #[test]
+    fn as_ptr() {
+        let strong = Strong::new(1);
+        let rcell = RCell::from(strong.clone());
+        let weak = RCell::from(Strong::downgrade(&strong));
+        assert_eq!(rcell.as_ptr(), Strong::as_ptr(&strong));
+        assert_eq!(weak.as_ptr(), rcell.as_ptr());
+        assert_eq!(weak.addr(), rcell.addr());
+        assert_eq!(Strong::strong_count(&strong), 2);
+        assert!(RCell::<u8>::empty().as_ptr().is_null());
+        assert_eq!(RCell::<str>::empty().addr(), 0);
+    }
 }
@@ -0,0 +1,83 @@
+//! Support code for the `#[cached]` attribute macro.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::{RCell, Strong};
+
+#[cfg(feature = "sync")]
+type Lock<T> = std::sync::Mutex<T>;
+#[cfg(not(feature = "sync"))]
+type Lock<T> = std::cell::RefCell<T>;
+
+/// Weak memoization table mapping keys to the last computed value.
+#[derive(Debug)]
+pub struct Memo<K, T>(Lock<Option<HashMap<K, RCell<T>>>>);
+
+impl<K: Hash + Eq, T> Memo<K, T> {
+    /// Creates an empty memoization table.
+    pub const fn new() -> Self {
+        Memo(Lock::new(None))
+    }
+
+    #[cfg(feature = "sync")]
+    fn with<R>(&self, f: impl FnOnce(&mut HashMap<K, RCell<T>>) -> R) -> R {
+        let mut lock = self.0.lock().unwrap_or_else(|poison| poison.into_inner());
+        f(lock.get_or_insert_with(HashMap::new))
+    }
+
+    #[cfg(not(feature = "sync"))]
+    fn with<R>(&self, f: impl FnOnce(&mut HashMap<K, RCell<T>>) -> R) -> R {
+        f(self.0.borrow_mut().get_or_insert_with(HashMap::new))
+    }
+
+    /// Returns the value stored under `key` when it is still alive, otherwise calls `f` and
+    /// stores its result weakly. The table is not locked while `f` runs.
+    pub fn get_or_insert_with(&self, key: K, f: impl FnOnce() -> Strong<T>) -> Strong<T> {
+        if let Some(strong) = self.with(|map| map.get(&key).and_then(RCell::request)) {
+            return strong;
+        }
+        let strong = f();
+        self.with(|map| {
+            map.retain(|_, cell| cell.refcount() > 0);
+            // a concurrent caller may have been faster
+            match map.get(&key).and_then(RCell::request) {
+                Some(existing) => existing,
+                None => {
+                    map.insert(key, RCell::from(Strong::downgrade(&strong)));
+                    strong
+                }
+            }
+        })
+    }
+}
+
+impl<K: Hash + Eq, T> Default for Memo<K, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Expands to a lookup in a function local memoization table, used by `#[cached]`.
+#[cfg(feature = "sync")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __memo {
+    ($key:ty, $value:ty, $keyval:expr, $f:expr) => {{
+        static MEMO: $crate::memo::Memo<$key, $value> = $crate::memo::Memo::new();
+        MEMO.get_or_insert_with($keyval, $f)
+    }};
+}
+
+/// Expands to a lookup in a function local memoization table, used by `#[cached]`.
+#[cfg(not(feature = "sync"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __memo {
+    ($key:ty, $value:ty, $keyval:expr, $f:expr) => {{
+        ::std::thread_local! {
+            static MEMO: $crate::memo::Memo<$key, $value> = const { $crate::memo::Memo::new() };
+        }
+        MEMO.with(|memo| memo.get_or_insert_with($keyval, $f))
+    }};
+}
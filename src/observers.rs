@@ -0,0 +1,108 @@
+//! Weakly held observer lists.
+
+use crate::{RCell, Strong};
+
+/// A list of weakly held listeners. Subscribing does not keep a listener alive, dropped
+/// listeners are removed while notifying.
+#[derive(Debug)]
+pub struct Observers<T: ?Sized> {
+    listeners: Vec<RCell<T>>,
+}
+
+impl<T: ?Sized> Observers<T> {
+    /// Creates an empty list.
+    pub fn new() -> Self {
+        Observers {
+            listeners: Vec::new(),
+        }
+    }
+
+    /// Returns the number of listeners, including dropped ones not yet removed.
+    pub fn len(&self) -> usize {
+        self.listeners.len()
+    }
+
+    /// Returns 'true' when there are no listeners.
+    pub fn is_empty(&self) -> bool {
+        self.listeners.is_empty()
+    }
+
+    /// Adds a listener, only a weak reference is kept.
+    pub fn subscribe(&mut self, listener: &Strong<T>) {
+        self.listeners
+            .push(RCell::from(Strong::downgrade(listener)));
+    }
+
+    /// Removes a listener. Returns 'true' when it was subscribed. Dropped listeners are left
+    /// for `notify()` to remove.
+    pub fn unsubscribe(&mut self, listener: &Strong<T>) -> bool {
+        let position = self.listeners.iter().position(|cell| {
+            cell.request()
+                .is_some_and(|other| Strong::ptr_eq(&other, listener))
+        });
+        position
+            .map(|position| self.listeners.remove(position))
+            .is_some()
+    }
+
+    /// Calls `f` for every live listener in subscription order and removes the dropped ones
+    /// in the same pass. Returns the number of listeners called.
+    pub fn notify(&mut self, mut f: impl FnMut(&T)) -> usize {
+        let mut called = 0;
+        self.listeners.retain(|cell| match cell.request() {
+            Some(listener) => {
+                f(&listener);
+                called += 1;
+                true
+            }
+            None => false,
+        });
+        called
+    }
+}
+
+impl<T: ?Sized> Default for Observers<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::Observers;
+    use crate::Strong;
+
+    #[test]
+    fn notify() {
+        let mut observers = Observers::<AtomicU32>::new();
+        let first = Strong::new(AtomicU32::new(0));
+        let second = Strong::new(AtomicU32::new(0));
+        observers.subscribe(&first);
+        observers.subscribe(&second);
+        observers.subscribe(&Strong::new(AtomicU32::new(0)));
+        assert_eq!(observers.len(), 3);
+
+        assert_eq!(
+            observers.notify(|counter| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }),
+            2
+        );
+        assert_eq!(observers.len(), 2);
+        assert!(observers.unsubscribe(&second));
+        drop(first);
+        assert_eq!(observers.notify(|_| {}), 0);
+        assert_eq!(second.load(Ordering::SeqCst), 1);
+        assert!(observers.is_empty());
+    }
+
+    #[test]
+    fn unsubscribe_foreign() {
+        let mut observers = Observers::<AtomicU32>::new();
+        observers.subscribe(&Strong::new(AtomicU32::new(0)));
+        assert!(!observers.unsubscribe(&Strong::new(AtomicU32::new(0))));
+        assert_eq!(observers.len(), 1);
+    }
+}
@@ -0,0 +1,73 @@
+//! Conversions from `Option<Strong<T>>` and `Option<Weak<T>>` into RCells.
+
+use crate::{RCell, Strong, Weak};
+
+/// Extension trait converting `Option<Strong<T>>` and `Option<Weak<T>>` into RCells, `None`
+/// becomes an empty RCell.
+pub trait OptionExt<T: ?Sized> {
+    /// Converts into a RCell holding the reference as is.
+    fn into_rcell(self) -> RCell<T>;
+
+    /// Converts into a RCell holding a weak reference.
+    fn into_weak_rcell(self) -> RCell<T>;
+
+    /// Creates a RCell holding a clone of the reference.
+    fn as_rcell(&self) -> RCell<T>;
+}
+
+impl<T: ?Sized> OptionExt<T> for Option<Strong<T>> {
+    fn into_rcell(self) -> RCell<T> {
+        self.map_or_else(RCell::default, RCell::from)
+    }
+
+    fn into_weak_rcell(self) -> RCell<T> {
+        self.as_ref().map_or_else(RCell::default, |strong| {
+            RCell::from(Strong::downgrade(strong))
+        })
+    }
+
+    fn as_rcell(&self) -> RCell<T> {
+        self.clone().into_rcell()
+    }
+}
+
+impl<T: ?Sized> OptionExt<T> for Option<Weak<T>> {
+    fn into_rcell(self) -> RCell<T> {
+        self.map_or_else(RCell::default, RCell::from)
+    }
+
+    fn into_weak_rcell(self) -> RCell<T> {
+        self.into_rcell()
+    }
+
+    fn as_rcell(&self) -> RCell<T> {
+        self.clone().into_rcell()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OptionExt;
+    use crate::{Strong, Weak};
+
+    #[test]
+    fn strong() {
+        let strong = Some(Strong::new(1));
+        let rcell = strong.as_rcell();
+        assert!(rcell.retained());
+        let weak = strong.clone().into_weak_rcell();
+        assert!(!weak.retained());
+        assert_eq!(*weak.request().unwrap(), 1);
+        assert!(strong.into_rcell().retained());
+        assert_eq!(None::<Strong<u8>>.into_rcell().request(), None);
+    }
+
+    #[test]
+    fn weak() {
+        let strong = Strong::new(1);
+        let weak = Some(Strong::downgrade(&strong));
+        assert!(!weak.as_rcell().retained());
+        assert_eq!(*weak.into_rcell().request().unwrap(), 1);
+        assert_eq!(None::<Weak<u8>>.into_weak_rcell().request(), None);
+    }
+}
@@ -0,0 +1,211 @@
+//! Registry for plugins which can be unloaded and reloaded while in use.
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{RCell, Strong, Weak};
+
+/// Stores named plugins behind RCells. Callers get `Strong` handles to a plugin, unloading
+/// or reloading a plugin removes it from the registry at once and then waits until all
+/// outstanding handles are dropped, that is until the old plugin is really gone.
+#[derive(Debug)]
+pub struct PluginRegistry<P: ?Sized> {
+    plugins: RwLock<HashMap<String, RCell<P>>>,
+}
+
+impl<P: ?Sized> Default for PluginRegistry<P> {
+    fn default() -> Self {
+        PluginRegistry {
+            plugins: RwLock::new(HashMap::new()),
+        }
+    }
+}
+
+impl<P: ?Sized> PluginRegistry<P> {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of loaded plugins.
+    pub fn len(&self) -> usize {
+        self.plugins
+            .read()
+            .unwrap_or_else(|poison| poison.into_inner())
+            .len()
+    }
+
+    /// Returns 'true' when no plugins are loaded.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Loads a plugin under `name`. A plugin previously loaded under this name is released
+    /// without waiting for it, its weak reference is returned.
+    pub fn load(&self, name: impl Into<String>, plugin: Strong<P>) -> Option<Weak<P>> {
+        let old = self
+            .plugins
+            .write()
+            .unwrap_or_else(|poison| poison.into_inner())
+            .insert(name.into(), RCell::from(plugin))?;
+        old.request().as_ref().map(Strong::downgrade)
+    }
+
+    /// Returns a handle to the plugin loaded under `name`.
+    pub fn get(&self, name: &str) -> Option<Strong<P>> {
+        self.plugins
+            .read()
+            .unwrap_or_else(|poison| poison.into_inner())
+            .get(name)?
+            .request()
+    }
+
+    /// Returns the names of all loaded plugins.
+    pub fn names(&self) -> Vec<String> {
+        self.plugins
+            .read()
+            .unwrap_or_else(|poison| poison.into_inner())
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    /// Unloads the plugin `name` and waits up to `timeout` for it to be dropped. Returns
+    /// 'true' when the plugin is gone, 'false' when handles are still outstanding after the
+    /// timeout or no such plugin was loaded.
+    pub fn unload(&self, name: &str, timeout: Duration) -> bool {
+        let old = self
+            .plugins
+            .write()
+            .unwrap_or_else(|poison| poison.into_inner())
+            .remove(name);
+        match old {
+            Some(mut old) => {
+                old.release();
+                wait_dropped(&old, timeout)
+            }
+            None => false,
+        }
+    }
+
+    /// Replaces the plugin `name` with `plugin` and waits up to `timeout` for the old one to
+    /// be dropped. New callers get the new plugin immediately. Returns 'true' when the old
+    /// plugin is gone or there was none.
+    pub fn reload(&self, name: impl Into<String>, plugin: Strong<P>, timeout: Duration) -> bool {
+        let old = self
+            .plugins
+            .write()
+            .unwrap_or_else(|poison| poison.into_inner())
+            .insert(name.into(), RCell::from(plugin));
+        match old {
+            Some(mut old) => {
+                old.release();
+                wait_dropped(&old, timeout)
+            }
+            None => true,
+        }
+    }
+}
+
+/// Longest pause between two checks in `wait_dropped()`.
+const MAX_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Polls a released cell until its value got dropped or the timeout passed. Nothing notifies
+/// when the last handle goes away, the pause between checks doubles up to `MAX_BACKOFF`.
+fn wait_dropped<P: ?Sized>(cell: &RCell<P>, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    let mut pause = Duration::from_micros(100);
+    while cell.refcount() > 0 {
+        let now = Instant::now();
+        if now >= deadline {
+            return false;
+        }
+        thread::sleep(pause.min(deadline - now));
+        pause = (pause * 2).min(MAX_BACKOFF);
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::PluginRegistry;
+    use crate::Strong;
+
+    trait Plugin: Send + Sync {
+        fn name(&self) -> &'static str;
+    }
+
+    struct V1;
+    impl Plugin for V1 {
+        fn name(&self) -> &'static str {
+            "v1"
+        }
+    }
+
+    struct V2;
+    impl Plugin for V2 {
+        fn name(&self) -> &'static str {
+            "v2"
+        }
+    }
+
+    #[test]
+    fn reload() {
+        let registry = Arc::new(PluginRegistry::<dyn Plugin>::new());
+        assert!(registry.reload("p", Strong::new(V1), Duration::ZERO));
+        let handle = registry.get("p").unwrap();
+
+        // the old plugin is still in use
+        assert!(!registry.reload("p", Strong::new(V2), Duration::from_millis(5)));
+        assert_eq!(registry.get("p").unwrap().name(), "v2");
+        assert_eq!(handle.name(), "v1");
+
+        drop(handle);
+        assert!(registry.unload("p", Duration::ZERO));
+        assert!(registry.is_empty());
+        assert!(!registry.unload("p", Duration::ZERO));
+    }
+
+    #[test]
+    fn unload_waits() {
+        let registry = Arc::new(PluginRegistry::<dyn Plugin>::new());
+        registry.load("p", Strong::new(V1));
+
+        // another thread holds a handle until told to drop it
+        let handle = registry.get("p").unwrap();
+        let (release, released) = mpsc::channel::<()>();
+        let caller = thread::spawn(move || {
+            released.recv().unwrap();
+            assert_eq!(handle.name(), "v1");
+        });
+
+        let (done, unloaded) = mpsc::channel();
+        let unloader = {
+            let registry = registry.clone();
+            thread::spawn(move || done.send(registry.unload("p", Duration::from_secs(10))))
+        };
+        assert!(unloaded.recv_timeout(Duration::from_millis(50)).is_err());
+        assert!(registry.get("p").is_none());
+
+        release.send(()).unwrap();
+        assert!(unloaded.recv_timeout(Duration::from_secs(10)).unwrap());
+        caller.join().unwrap();
+        unloader.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn load() {
+        let registry = PluginRegistry::<dyn Plugin>::new();
+        assert!(registry.load("p", Strong::new(V1)).is_none());
+        let old = registry.load("p", Strong::new(V2)).unwrap();
+        assert!(old.upgrade().is_none());
+        assert_eq!(registry.names(), vec![String::from("p")]);
+    }
+}
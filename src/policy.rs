@@ -0,0 +1,296 @@
+//! Cells consulting a retention policy.
+
+use std::ops::Deref;
+use std::time::{Duration, Instant};
+
+use crate::{CachePolicy, CostOf, RCell, Strong, Usage};
+
+/// Access statistics of a `PolicyCell`, passed to its `RetentionPolicy`.
+#[derive(Debug, Clone, Copy)]
+pub struct AccessInfo {
+    /// When the cell was created.
+    pub created: Instant,
+    /// When the value was last requested.
+    pub last_access: Instant,
+    /// Number of requests.
+    pub accesses: u64,
+    /// Approximate cost of the value in bytes, 0 when unknown.
+    pub cost: usize,
+}
+
+impl AccessInfo {
+    fn new(cost: usize) -> Self {
+        let now = Instant::now();
+        AccessInfo {
+            created: now,
+            last_access: now,
+            accesses: 0,
+            cost,
+        }
+    }
+
+    /// Returns the time since the cell was created.
+    pub fn age(&self) -> Duration {
+        self.created.elapsed()
+    }
+
+    /// Returns the time since the last request.
+    pub fn idle(&self) -> Duration {
+        self.last_access.elapsed()
+    }
+}
+
+/// Decides whether a `PolicyCell` keeps its value retained. Implemented for closures taking
+/// `&AccessInfo`.
+pub trait RetentionPolicy {
+    /// Returns 'true' when the value should stay retained.
+    fn should_retain(&self, info: &AccessInfo) -> bool;
+}
+
+impl<F: Fn(&AccessInfo) -> bool> RetentionPolicy for F {
+    fn should_retain(&self, info: &AccessInfo) -> bool {
+        self(info)
+    }
+}
+
+/// Retains values which were requested within the given duration.
+#[derive(Debug, Clone, Copy)]
+pub struct MaxIdle(pub Duration);
+
+impl RetentionPolicy for MaxIdle {
+    fn should_retain(&self, info: &AccessInfo) -> bool {
+        info.idle() <= self.0
+    }
+}
+
+/// Retains values which were requested at least the given number of times.
+#[derive(Debug, Clone, Copy)]
+pub struct MinAccesses(pub u64);
+
+impl RetentionPolicy for MinAccesses {
+    fn should_retain(&self, info: &AccessInfo) -> bool {
+        info.accesses >= self.0
+    }
+}
+
+/// A RCell which asks a `RetentionPolicy` whether to keep its value retained, on every
+/// request and on `sweep()`. Derefs to the inner `RCell<T>` for read only access.
+#[derive(Debug)]
+pub struct PolicyCell<T: ?Sized, P> {
+    cell: RCell<T>,
+    policy: P,
+    info: AccessInfo,
+}
+
+impl<T, P: RetentionPolicy> PolicyCell<T, P> {
+    /// Creates a new strong PolicyCell with an unknown cost.
+    pub fn new(value: T, policy: P) -> Self {
+        Self::from_rcell(RCell::new(value), policy, 0)
+    }
+}
+
+impl<T: ?Sized, P: RetentionPolicy> PolicyCell<T, P> {
+    /// Wraps an existing RCell, `cost` is passed to the policy.
+    pub fn from_rcell(cell: RCell<T>, policy: P, cost: usize) -> Self {
+        PolicyCell {
+            cell,
+            policy,
+            info: AccessInfo::new(cost),
+        }
+    }
+
+    /// Creates a new strong PolicyCell from a `Strong<T>` with the cost of its value.
+    pub fn with_cost(strong: Strong<T>, policy: P) -> Self
+    where
+        T: CostOf,
+    {
+        let cost = strong.cost_of();
+        Self::from_rcell(RCell::from(strong), policy, cost)
+    }
+
+    /// Returns the access statistics.
+    pub fn info(&self) -> &AccessInfo {
+        &self.info
+    }
+
+    /// Returns the policy.
+    pub fn policy(&self) -> &P {
+        &self.policy
+    }
+
+    /// Tries to get the value and records the access. Afterwards the cell is retained or
+    /// released as the policy decides.
+    pub fn request(&mut self) -> Option<Strong<T>> {
+        let strong = self.cell.request()?;
+        self.info.last_access = Instant::now();
+        self.info.accesses += 1;
+        self.apply();
+        Some(strong)
+    }
+
+    /// Asks the policy without recording an access, typically called periodically over a
+    /// set of cells. Returns 'true' when the cell stays retained.
+    pub fn sweep(&mut self) -> bool {
+        self.apply();
+        self.cell.retained()
+    }
+
+    fn apply(&mut self) {
+        if self.policy.should_retain(&self.info) {
+            self.cell.retain();
+        } else if self.cell.retained() {
+            self.cell.release();
+        }
+    }
+
+    /// Unwraps the inner RCell.
+    pub fn into_inner(self) -> RCell<T> {
+        self.cell
+    }
+}
+
+impl<T: ?Sized, P> Deref for PolicyCell<T, P> {
+    type Target = RCell<T>;
+
+    fn deref(&self) -> &RCell<T> {
+        &self.cell
+    }
+}
+
+/// TinyLFU admission for `RCellCache`: key frequencies, including those of misses, are
+/// estimated with a count-min sketch and a new key only replaces the least recently used
+/// entry when it was requested more often. Keys which are requested once don't push hot
+/// entries out. The counters are halved periodically so that the estimates follow changes
+/// in popularity.
+#[derive(Debug, Clone)]
+pub struct TinyLfu {
+    counters: Vec<u8>,
+    mask: usize,
+    additions: usize,
+    sample: usize,
+}
+
+/// Multipliers selecting the counter of a key hash in each sketch row.
+const SKETCH_SEEDS: [u64; 4] = [
+    0x9e37_79b9_7f4a_7c15,
+    0xc2b2_ae3d_27d4_eb4f,
+    0x1656_67b1_9e37_79f9,
+    0x27d4_eb2f_1656_67c5,
+];
+
+impl TinyLfu {
+    /// Creates a policy sized for a cache retaining `capacity` values.
+    pub fn new(capacity: usize) -> Self {
+        let width = (capacity * 4).next_power_of_two().max(16);
+        TinyLfu {
+            counters: vec![0; width * SKETCH_SEEDS.len()],
+            mask: width - 1,
+            additions: 0,
+            sample: width * 10,
+        }
+    }
+
+    fn index(&self, row: usize, key_hash: u64) -> usize {
+        let hash = key_hash.wrapping_mul(SKETCH_SEEDS[row]);
+        row * (self.mask + 1) + ((hash >> 32) as usize & self.mask)
+    }
+
+    /// Returns the estimated number of recent requests for a key hash.
+    pub fn frequency(&self, key_hash: u64) -> u8 {
+        (0..SKETCH_SEEDS.len())
+            .map(|row| self.counters[self.index(row, key_hash)])
+            .min()
+            .unwrap_or(0)
+    }
+}
+
+impl CachePolicy for TinyLfu {
+    /// Evicts in least recently used order.
+    fn score(&self, usage: &Usage) -> u64 {
+        usage.last_used
+    }
+
+    fn record(&mut self, key_hash: u64) {
+        for row in 0..SKETCH_SEEDS.len() {
+            let index = self.index(row, key_hash);
+            self.counters[index] = self.counters[index].saturating_add(1).min(15);
+        }
+        self.additions += 1;
+        if self.additions >= self.sample {
+            self.counters.iter_mut().for_each(|counter| *counter /= 2);
+            self.additions /= 2;
+        }
+    }
+
+    fn admit(&self, candidate: u64, victim: u64) -> bool {
+        self.frequency(candidate) > self.frequency(victim)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{AccessInfo, MaxIdle, MinAccesses, PolicyCell, TinyLfu};
+    use crate::{RCellCache, Strong};
+
+    #[test]
+    fn min_accesses() {
+        let mut cell = PolicyCell::new(1, MinAccesses(2));
+        let strong = cell.request().unwrap();
+        assert!(!cell.retained());
+        assert!(!cell.sweep());
+        cell.request();
+        assert!(cell.retained());
+        assert_eq!(cell.info().accesses, 2);
+        drop(strong);
+        assert!(cell.sweep());
+    }
+
+    #[test]
+    fn max_idle() {
+        let mut cell = PolicyCell::new(1, MaxIdle(Duration::from_millis(10)));
+        assert!(cell.sweep());
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(!cell.sweep());
+        assert_eq!(cell.request(), None);
+    }
+
+    #[test]
+    fn cost() {
+        let mut cell = PolicyCell::with_cost(Strong::new(vec![0u8; 64]), |info: &AccessInfo| {
+            info.cost < 64
+        });
+        let strong = cell.request().unwrap();
+        assert!(!cell.retained());
+        assert!(cell.info().cost >= 64);
+        drop(strong);
+    }
+
+    #[test]
+    fn tiny_lfu() {
+        let mut cache = RCellCache::with_policy(2, TinyLfu::new(2));
+        let hot: Vec<_> = ["a", "b"].map(|key| cache.insert(key, 0)).into();
+        for _ in 0..3 {
+            cache.get("a");
+            cache.get("b");
+        }
+        // a one hit wonder is not admitted
+        let once = cache.insert("once", 1);
+        assert!(cache
+            .iter()
+            .all(|(key, cell)| cell.retained() != (*key == "once")));
+        assert_eq!(cache.peek("once"), Some(once));
+
+        // repeated misses make a key popular enough
+        for _ in 0..5 {
+            assert_eq!(cache.get("popular"), None);
+        }
+        cache.insert("popular", 2);
+        assert_eq!(cache.retained(), 2);
+        assert!(cache
+            .iter()
+            .any(|(key, cell)| *key == "popular" && cell.retained()));
+        drop(hot);
+    }
+}
@@ -0,0 +1,254 @@
+//! Memory pressure watcher releasing registered cells and retainers.
+//!
+//! ```
+//! use std::sync::{Arc, Mutex};
+//! use rcell::*;
+//!
+//! let cell = Arc::new(Mutex::new(RCell::new(vec![0u8; 1024])));
+//! let watcher = PressureWatcher::new(PressureSource::Callback(Box::new(|| true)), 0);
+//! watcher.register(&cell);
+//! assert!(watcher.check());
+//! assert!(!cell.lock().unwrap().retained());
+//! ```
+
+use std::sync::{Arc, Mutex, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::{PriorityRetainer, RCell};
+
+/// Something that can give up memory when asked to.
+pub trait Relieve: Send {
+    /// Releases memory, keeping at most `target` entries strong where this applies.
+    fn relieve(&mut self, target: usize);
+}
+
+impl<T: ?Sized + Send + Sync> Relieve for RCell<T> {
+    /// Releases the cell, the target is ignored.
+    fn relieve(&mut self, _target: usize) {
+        self.release();
+    }
+}
+
+impl<T: Send + Sync, P: Ord + Send> Relieve for PriorityRetainer<T, P> {
+    /// Releases all but the `target` highest priority cells, see
+    /// `PriorityRetainer::shrink_to()`.
+    fn relieve(&mut self, target: usize) {
+        self.shrink_to(target);
+    }
+}
+
+/// How memory pressure is detected.
+pub enum PressureSource {
+    /// Pressure when the resident set size of the process exceeds `limit` bytes, read from
+    /// `/proc/self/status` (Linux only).
+    Rss {
+        /// Limit in bytes.
+        limit: usize,
+    },
+    /// Pressure when the 10 second average of the Linux pressure stall information
+    /// (`/proc/pressure/memory` or a cgroup's `memory.pressure`) exceeds `avg10` percent.
+    Psi {
+        /// The PSI file to read.
+        path: String,
+        /// Threshold in percent.
+        avg10: f32,
+    },
+    /// Pressure when the callback returns 'true'.
+    Callback(Box<dyn Fn() -> bool + Send + Sync>),
+}
+
+impl std::fmt::Debug for PressureSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PressureSource::Rss { limit } => f.debug_struct("Rss").field("limit", limit).finish(),
+            PressureSource::Psi { path, avg10 } => f
+                .debug_struct("Psi")
+                .field("path", path)
+                .field("avg10", avg10)
+                .finish(),
+            PressureSource::Callback(_) => f.write_str("Callback"),
+        }
+    }
+}
+
+impl PressureSource {
+    /// Returns 'true' when memory pressure is detected. Sources which can't be read report no
+    /// pressure.
+    pub fn under_pressure(&self) -> bool {
+        match self {
+            PressureSource::Rss { limit } => rss().is_some_and(|rss| rss > *limit),
+            PressureSource::Psi { path, avg10 } => psi_avg10(path).is_some_and(|avg| avg > *avg10),
+            PressureSource::Callback(callback) => callback(),
+        }
+    }
+}
+
+/// Returns the resident set size of this process in bytes.
+fn rss() -> Option<usize> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kb: usize = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+/// Returns the 'some avg10' value of a PSI file.
+fn psi_avg10(path: &str) -> Option<f32> {
+    let psi = std::fs::read_to_string(path).ok()?;
+    let some = psi.lines().find(|line| line.starts_with("some "))?;
+    some.split_whitespace()
+        .find_map(|field| field.strip_prefix("avg10="))?
+        .parse()
+        .ok()
+}
+
+/// Watches for memory pressure and asks registered cells and retainers to release memory when
+/// pressure is detected. Checks are done by calling `check()` or periodically on a thread
+/// started by `spawn()`.
+#[derive(Debug)]
+pub struct PressureWatcher {
+    source: PressureSource,
+    target: usize,
+    targets: Mutex<Vec<Weak<Mutex<dyn Relieve>>>>,
+}
+
+impl PressureWatcher {
+    /// Creates a watcher, on pressure the registered targets are relieved down to `target`
+    /// strong entries.
+    pub fn new(source: PressureSource, target: usize) -> Self {
+        PressureWatcher {
+            source,
+            target,
+            targets: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Registers a target. Only a weak reference is kept, targets which got dropped are
+    /// removed automatically.
+    pub fn register<R: Relieve + 'static>(&self, target: &Arc<Mutex<R>>) {
+        let target: Arc<Mutex<dyn Relieve>> = target.clone();
+        self.lock().push(Arc::downgrade(&target));
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Weak<Mutex<dyn Relieve>>>> {
+        self.targets
+            .lock()
+            .unwrap_or_else(|poison| poison.into_inner())
+    }
+
+    /// Checks for memory pressure once and relieves all targets when detected. Returns
+    /// 'true' when there was pressure.
+    pub fn check(&self) -> bool {
+        let pressure = self.source.under_pressure();
+        let mut live = Vec::new();
+        self.lock().retain(|target| match target.upgrade() {
+            Some(target) => {
+                live.push(target);
+                true
+            }
+            None => false,
+        });
+        // relieve without holding our own lock, targets may register new ones
+        if pressure {
+            for target in live {
+                target
+                    .lock()
+                    .unwrap_or_else(|poison| poison.into_inner())
+                    .relieve(self.target);
+            }
+        }
+        pressure
+    }
+
+    /// Starts a thread calling `check()` every `interval`. The thread ends when the last
+    /// reference to the watcher is dropped.
+    pub fn spawn(self: &Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        let watcher = Arc::downgrade(self);
+        thread::spawn(move || {
+            while let Some(watcher) = watcher.upgrade() {
+                watcher.check();
+                drop(watcher);
+                thread::sleep(interval);
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use super::{PressureSource, PressureWatcher};
+    use crate::{PriorityRetainer, RCell};
+
+    #[test]
+    fn callback() {
+        let pressure = Arc::new(AtomicBool::new(false));
+        let flag = pressure.clone();
+        let watcher = PressureWatcher::new(
+            PressureSource::Callback(Box::new(move || flag.load(Ordering::SeqCst))),
+            1,
+        );
+
+        let cell = Arc::new(Mutex::new(RCell::new(1)));
+        let retainer = Arc::new(Mutex::new(PriorityRetainer::new(2)));
+        let strongs: Vec<_> = (0..3)
+            .map(|i| {
+                let cell = RCell::new(i);
+                let strong = cell.request().unwrap();
+                retainer.lock().unwrap().insert(cell, i);
+                strong
+            })
+            .collect();
+        watcher.register(&cell);
+        watcher.register(&retainer);
+
+        assert!(!watcher.check());
+        assert!(cell.lock().unwrap().retained());
+
+        pressure.store(true, Ordering::SeqCst);
+        assert!(watcher.check());
+        assert!(!cell.lock().unwrap().retained());
+        let retainer = retainer.lock().unwrap();
+        assert_eq!(
+            retainer.iter().filter(|(_, cell)| cell.retained()).count(),
+            1
+        );
+        drop(strongs);
+    }
+
+    #[test]
+    fn dropped_targets() {
+        let watcher = PressureWatcher::new(PressureSource::Callback(Box::new(|| true)), 0);
+        let cell = Arc::new(Mutex::new(RCell::new(1)));
+        watcher.register(&cell);
+        drop(cell);
+        assert!(watcher.check());
+        assert!(watcher.lock().is_empty());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn rss() {
+        assert!(PressureSource::Rss { limit: 0 }.under_pressure());
+        assert!(!PressureSource::Rss { limit: usize::MAX }.under_pressure());
+    }
+
+    #[test]
+    fn spawn() {
+        let watcher = Arc::new(PressureWatcher::new(
+            PressureSource::Callback(Box::new(|| true)),
+            0,
+        ));
+        let cell = Arc::new(Mutex::new(RCell::new(1)));
+        watcher.register(&cell);
+        let thread = watcher.spawn(Duration::from_millis(10));
+        while cell.lock().unwrap().retained() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        drop(watcher);
+        thread.join().unwrap();
+    }
+}
@@ -0,0 +1,467 @@
+//! Registry holding one cell per type.
+
+use std::any::TypeId;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::{AnyRCell, AnyValue, DynAny, State, Strong, Weak};
+
+type Shard = RwLock<HashMap<TypeId, AnyRCell>>;
+
+/// Number of shards of `TypeRegistry::new()`.
+const DEFAULT_SHARDS: usize = 16;
+
+/// A map from types to one `AnyRCell` each, the classic extension map pattern. Unlike a
+/// plain type map values can be released, they stay available as long as someone else keeps
+/// them alive and can be retained again.
+///
+/// The map is split into shards which are locked independently, registrations and lookups
+/// of different types from many threads don't serialize on a single lock.
+///
+/// Unlike `RCellCache::warm_keys()` there is no snapshot to persist and restore the
+/// registry across restarts: entries are keyed by `TypeId`, which is not stable between
+/// builds, and the values are type erased. Services repopulate it at startup with
+/// `get_or_insert_with()` for the types they need.
+#[derive(Debug)]
+pub struct TypeRegistry {
+    hasher: RandomState,
+    shards: Box<[Shard]>,
+    // bumped whenever entries are added, replaced or removed, readers refresh on change
+    epoch: AtomicU64,
+}
+
+impl TypeRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::with_shards(DEFAULT_SHARDS)
+    }
+
+    /// Creates an empty registry split into `shards` shards.
+    ///
+    /// # Panics
+    ///
+    /// When `shards` is zero.
+    pub fn with_shards(shards: usize) -> Self {
+        assert!(shards > 0, "TypeRegistry needs at least one shard");
+        TypeRegistry {
+            hasher: RandomState::new(),
+            shards: (0..shards).map(|_| RwLock::default()).collect(),
+            epoch: AtomicU64::new(0),
+        }
+    }
+
+    fn shard(&self, id: TypeId) -> &Shard {
+        &self.shards[self.hasher.hash_one(id) as usize % self.shards.len()]
+    }
+
+    fn read(&self, id: TypeId) -> RwLockReadGuard<'_, HashMap<TypeId, AnyRCell>> {
+        self.shard(id)
+            .read()
+            .unwrap_or_else(|poison| poison.into_inner())
+    }
+
+    fn write(&self, id: TypeId) -> RwLockWriteGuard<'_, HashMap<TypeId, AnyRCell>> {
+        self.shard(id)
+            .write()
+            .unwrap_or_else(|poison| poison.into_inner())
+    }
+
+    fn read_all(&self) -> impl Iterator<Item = RwLockReadGuard<'_, HashMap<TypeId, AnyRCell>>> {
+        self.shards
+            .iter()
+            .map(|shard| shard.read().unwrap_or_else(|poison| poison.into_inner()))
+    }
+
+    fn write_all(&self) -> impl Iterator<Item = RwLockWriteGuard<'_, HashMap<TypeId, AnyRCell>>> {
+        self.shards
+            .iter()
+            .map(|shard| shard.write().unwrap_or_else(|poison| poison.into_inner()))
+    }
+
+    /// Returns the number of registered types, including released ones whose value may be
+    /// gone.
+    pub fn len(&self) -> usize {
+        self.read_all().map(|cells| cells.len()).sum()
+    }
+
+    /// Returns 'true' when no types are registered.
+    pub fn is_empty(&self) -> bool {
+        self.read_all().all(|cells| cells.is_empty())
+    }
+
+    /// Stores `value` as strong reference for its type, replacing the previous one.
+    pub fn set<T: AnyValue>(&self, value: T) -> Strong<T> {
+        let strong = Strong::new(value);
+        self.set_strong(strong.clone());
+        strong
+    }
+
+    /// Stores a `Strong<T>` for its type, replacing the previous one.
+    pub fn set_strong<T: AnyValue>(&self, strong: Strong<T>) {
+        self.replace(TypeId::of::<T>(), AnyRCell::from_strong(strong));
+    }
+
+    /// Stores a `Weak<T>` for its type, replacing the previous one.
+    pub fn set_weak<T: AnyValue>(&self, weak: Weak<T>) {
+        self.replace(TypeId::of::<T>(), AnyRCell::from_weak(weak));
+    }
+
+    fn replace(&self, id: TypeId, cell: AnyRCell) -> Option<AnyRCell> {
+        let replaced = self.write(id).insert(id, cell);
+        self.changed();
+        replaced
+    }
+
+    fn changed(&self) {
+        self.epoch.fetch_add(1, Ordering::Release);
+    }
+
+    /// Returns the value of type `T` when it is alive.
+    pub fn get<T: AnyValue>(&self) -> Option<Strong<T>> {
+        self.read(TypeId::of::<T>())
+            .get(&TypeId::of::<T>())?
+            .downcast_request()
+    }
+
+    /// Returns 'true' when a value of type `T` is alive.
+    pub fn contains<T: AnyValue>(&self) -> bool {
+        self.get::<T>().is_some()
+    }
+
+    /// Returns the value of type `T`, calls `f` and stores its result when there is none.
+    ///
+    /// `f` runs without holding any lock and may look up or create other types. When two
+    /// threads race on the same type, the value stored first wins and is returned to both.
+    pub fn get_or_insert_with<T: AnyValue>(&self, f: impl FnOnce() -> T) -> Strong<T> {
+        if let Some(strong) = self.get::<T>() {
+            return strong;
+        }
+        let new = Strong::new(f());
+        let mut cells = self.write(TypeId::of::<T>());
+        // another thread may have inserted meanwhile
+        if let Some(strong) = cells
+            .get(&TypeId::of::<T>())
+            .and_then(AnyRCell::downcast_request)
+        {
+            return strong;
+        }
+        cells.insert(TypeId::of::<T>(), AnyRCell::from_strong(new.clone()));
+        self.changed();
+        new
+    }
+
+    /// Retains the value of type `T`, see `RCell::retain()`.
+    pub fn retain<T: AnyValue>(&self) -> Option<Strong<T>> {
+        self.write(TypeId::of::<T>())
+            .get_mut(&TypeId::of::<T>())?
+            .downcast_retain()
+    }
+
+    /// Releases the value of type `T`, see `RCell::release()`.
+    pub fn release<T: AnyValue>(&self) {
+        if let Some(cell) = self.write(TypeId::of::<T>()).get_mut(&TypeId::of::<T>()) {
+            cell.release();
+        }
+    }
+
+    /// Removes the entry for type `T`, returns its value when alive.
+    pub fn remove<T: AnyValue>(&self) -> Option<Strong<T>> {
+        let removed = self.write(TypeId::of::<T>()).remove(&TypeId::of::<T>());
+        self.changed();
+        removed?.downcast_request()
+    }
+
+    /// Returns a reader for read mostly workloads. It keeps its own copy of the entries and
+    /// looks values up without taking any lock, the copy is refreshed only after entries were
+    /// added, replaced or removed. Each thread should use its own reader.
+    pub fn reader(&self) -> RegistryReader<'_> {
+        RegistryReader {
+            registry: self,
+            epoch: self.epoch.load(Ordering::Acquire),
+            cells: self.weak_cells(),
+        }
+    }
+
+    fn weak_cells(&self) -> HashMap<TypeId, Weak<DynAny>> {
+        self.read_all()
+            .flat_map(|cells| {
+                cells
+                    .iter()
+                    .filter_map(|(id, cell)| match cell.state() {
+                        State::Strong(strong) => Some((*id, Strong::downgrade(strong))),
+                        State::Weak(weak) => Some((*id, weak.clone())),
+                        State::Empty => None,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// Returns a scope whose registrations are removed again when it is dropped, so tests
+    /// and plugins can't leak entries into a long lived registry.
+    pub fn scope(&self) -> RegistryScope<'_> {
+        RegistryScope {
+            registry: self,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Releases all values.
+    pub fn release_all(&self) {
+        for mut cells in self.write_all() {
+            cells.values_mut().for_each(|cell| cell.release());
+        }
+    }
+
+    /// Removes the entries whose value got dropped, returns how many were removed.
+    pub fn purge(&self) -> usize {
+        self.write_all()
+            .map(|mut cells| {
+                let before = cells.len();
+                cells.retain(|_, cell| cell.refcount() > 0);
+                if cells.len() != before {
+                    self.changed();
+                }
+                before - cells.len()
+            })
+            .sum()
+    }
+}
+
+/// Lock free lookups into a `TypeRegistry`, returned by `TypeRegistry::reader()`.
+#[derive(Debug)]
+pub struct RegistryReader<'a> {
+    registry: &'a TypeRegistry,
+    epoch: u64,
+    cells: HashMap<TypeId, Weak<DynAny>>,
+}
+
+impl RegistryReader<'_> {
+    /// Returns the value of type `T` when it is alive, see `TypeRegistry::get()`. Only takes
+    /// the registry locks when entries changed since the last lookup.
+    pub fn get<T: AnyValue>(&mut self) -> Option<Strong<T>> {
+        let epoch = self.registry.epoch.load(Ordering::Acquire);
+        if epoch != self.epoch {
+            self.cells = self.registry.weak_cells();
+            self.epoch = epoch;
+        }
+        self.cells
+            .get(&TypeId::of::<T>())?
+            .upgrade()?
+            .downcast::<T>()
+            .ok()
+    }
+}
+
+/// Guard returned by `TypeRegistry::scope()`. Registrations made through the scope are
+/// undone when the guard drops: the entries they replaced are restored, entries for types
+/// which were not registered before are removed. Registrations replaced in the meantime
+/// are left alone.
+#[derive(Debug)]
+pub struct RegistryScope<'a> {
+    registry: &'a TypeRegistry,
+    entries: Vec<(TypeId, Weak<DynAny>, Option<AnyRCell>)>,
+}
+
+impl Default for TypeRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RegistryScope<'_> {
+    /// Returns the registry, lookups go there.
+    pub fn registry(&self) -> &TypeRegistry {
+        self.registry
+    }
+
+    /// Stores `value` for its type until the scope ends, see `TypeRegistry::set()`.
+    pub fn set<T: AnyValue>(&mut self, value: T) -> Strong<T> {
+        let strong = Strong::new(value);
+        self.set_strong(strong.clone());
+        strong
+    }
+
+    /// Stores a `Strong<T>` for its type until the scope ends.
+    pub fn set_strong<T: AnyValue>(&mut self, strong: Strong<T>) {
+        let weak: Weak<DynAny> = Strong::<T>::downgrade(&strong);
+        let replaced = self
+            .registry
+            .replace(TypeId::of::<T>(), AnyRCell::from_strong(strong));
+        self.entries.push((TypeId::of::<T>(), weak, replaced));
+    }
+
+    /// Stores a `Weak<T>` for its type until the scope ends.
+    pub fn set_weak<T: AnyValue>(&mut self, weak: Weak<T>) {
+        let erased: Weak<DynAny> = weak.clone();
+        let replaced = self
+            .registry
+            .replace(TypeId::of::<T>(), AnyRCell::from_weak(weak));
+        self.entries.push((TypeId::of::<T>(), erased, replaced));
+    }
+}
+
+impl Drop for RegistryScope<'_> {
+    fn drop(&mut self) {
+        // newest first, nested overrides of one type unwind in order
+        while let Some((id, weak, replaced)) = self.entries.pop() {
+            let mut cells = self.registry.write(id);
+            let ours = cells.get(&id).is_some_and(|cell| match cell.state() {
+                State::Strong(strong) => Weak::ptr_eq(&Strong::downgrade(strong), &weak),
+                State::Weak(other) => Weak::ptr_eq(other, &weak),
+                State::Empty => false,
+            });
+            if ours {
+                match replaced {
+                    Some(replaced) => cells.insert(id, replaced),
+                    None => cells.remove(&id),
+                };
+                self.registry.changed();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TypeRegistry;
+    use crate::Strong;
+
+    #[derive(Debug, PartialEq)]
+    struct Config(u32);
+
+    #[test]
+    fn per_type() {
+        let registry = TypeRegistry::new();
+        registry.set(Config(1));
+        registry.set(String::from("foo"));
+        assert_eq!(*registry.get::<Config>().unwrap(), Config(1));
+        assert_eq!(*registry.get::<String>().unwrap(), "foo");
+        assert_eq!(registry.get::<u32>(), None);
+        registry.set(Config(2));
+        assert_eq!(registry.len(), 2);
+        assert_eq!(*registry.get::<Config>().unwrap(), Config(2));
+        assert_eq!(*registry.remove::<String>().unwrap(), "foo");
+        assert!(!registry.contains::<String>());
+    }
+
+    #[test]
+    fn release() {
+        let registry = TypeRegistry::with_shards(1);
+        let config = registry.get_or_insert_with(|| Config(1));
+        assert_eq!(registry.get_or_insert_with(|| Config(2)), config);
+        registry.release::<Config>();
+        assert_eq!(registry.get::<Config>(), Some(config.clone()));
+        assert_eq!(registry.retain::<Config>(), Some(config.clone()));
+        drop(config);
+        registry.release_all();
+        assert_eq!(registry.get::<Config>(), None);
+        assert_eq!(registry.purge(), 1);
+
+        let weak = Strong::new(3u8);
+        registry.set_weak(Strong::downgrade(&weak));
+        assert!(registry.contains::<u8>());
+    }
+
+    #[test]
+    fn scope() {
+        let registry = TypeRegistry::new();
+        registry.set(Config(1));
+        {
+            let mut scope = registry.scope();
+            scope.set(String::from("plugin"));
+            scope.set(Config(2));
+            let keep = Strong::new(3u8);
+            scope.set_weak(Strong::downgrade(&keep));
+            assert_eq!(*scope.registry().get::<Config>().unwrap(), Config(2));
+            scope.set(Config(3));
+            assert!(registry.contains::<u8>());
+            // replaced after the scope registered it, stays
+            registry.set(String::from("host"));
+        }
+        assert_eq!(registry.len(), 2);
+        assert_eq!(registry.get::<Config>().as_deref(), Some(&Config(1)));
+        assert_eq!(*registry.get::<String>().unwrap(), "host");
+        assert!(!registry.contains::<u8>());
+    }
+
+    #[test]
+    fn reader() {
+        let registry = TypeRegistry::new();
+        let config = registry.set(Config(1));
+        let mut reader = registry.reader();
+        assert_eq!(reader.get::<Config>(), Some(config));
+        assert_eq!(reader.get::<String>(), None);
+        registry.set(String::from("foo"));
+        assert_eq!(*reader.get::<String>().unwrap(), "foo");
+        registry.set(Config(2));
+        assert_eq!(*reader.get::<Config>().unwrap(), Config(2));
+        registry.release::<Config>();
+        assert_eq!(reader.get::<Config>(), None);
+        registry.remove::<String>();
+        assert_eq!(reader.get::<String>(), None);
+    }
+
+    #[test]
+    fn concurrent() {
+        use std::sync::Arc;
+
+        struct Slot<const N: usize>;
+        let registry = Arc::new(TypeRegistry::new());
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let registry = registry.clone();
+                std::thread::spawn(move || {
+                    registry.get_or_insert_with(|| Slot::<0>);
+                    registry.get_or_insert_with(|| Slot::<1>);
+                    registry.get_or_insert_with(|| Slot::<2>);
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(registry.len(), 3);
+    }
+
+    #[test]
+    fn nested_factory() {
+        struct Service(Strong<Config>);
+        // both types share the only shard
+        let registry = TypeRegistry::with_shards(1);
+        let service =
+            registry.get_or_insert_with(|| Service(registry.get_or_insert_with(|| Config(1))));
+        assert_eq!(*service.0, Config(1));
+        assert_eq!(registry.len(), 2);
+    }
+
+    #[test]
+    fn nested_sharded() {
+        struct Level<const N: usize>(u32);
+        // a chain of four factories over two randomly assigned shards, some of them
+        // necessarily share a shard
+        let registry = TypeRegistry::with_shards(2);
+        let top = registry.get_or_insert_with(|| {
+            Level::<3>(
+                registry
+                    .get_or_insert_with(|| {
+                        Level::<2>(
+                            registry
+                                .get_or_insert_with(|| {
+                                    Level::<1>(registry.get_or_insert_with(|| Level::<0>(1)).0 + 1)
+                                })
+                                .0
+                                + 1,
+                        )
+                    })
+                    .0
+                    + 1,
+            )
+        });
+        assert_eq!(top.0, 4);
+        assert_eq!(registry.len(), 4);
+    }
+}
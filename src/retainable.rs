@@ -0,0 +1,123 @@
+//! Object safe lifecycle interface and whole working set operations.
+
+use crate::{RCell, SmallRCell};
+
+/// Object safe interface to the lifecycle of a cell. This allows operating on heterogeneous
+/// collections of cells, for example a `Vec<Box<dyn Retainable>>` holding RCells of
+/// different types.
+pub trait Retainable {
+    /// Returns 'true' when the cell holds a strong reference.
+    fn retained(&self) -> bool;
+
+    /// Tries to upgrade the cell to a strong reference, see `RCell::retain()`. Returns
+    /// 'true' on success.
+    fn retain(&mut self) -> bool;
+
+    /// Downgrades the cell, see `RCell::release()`.
+    fn release(&mut self);
+
+    /// Removes the reference from the cell, see `RCell::remove()`.
+    fn remove(&mut self);
+}
+
+impl<T: ?Sized> Retainable for RCell<T> {
+    fn retained(&self) -> bool {
+        RCell::retained(self)
+    }
+
+    fn retain(&mut self) -> bool {
+        RCell::retain(self).is_some()
+    }
+
+    fn release(&mut self) {
+        RCell::release(self);
+    }
+
+    fn remove(&mut self) {
+        RCell::remove(self);
+    }
+}
+
+impl<T: Copy> Retainable for SmallRCell<T> {
+    fn retained(&self) -> bool {
+        SmallRCell::retained(self)
+    }
+
+    fn retain(&mut self) -> bool {
+        // an inline value is retained already and stays inline
+        SmallRCell::is_inline(self) || SmallRCell::retain(self).is_some()
+    }
+
+    fn release(&mut self) {
+        SmallRCell::release(self);
+    }
+
+    fn remove(&mut self) {
+        SmallRCell::remove(self);
+    }
+}
+
+impl<C: Retainable + ?Sized> Retainable for Box<C> {
+    fn retained(&self) -> bool {
+        (**self).retained()
+    }
+
+    fn retain(&mut self) -> bool {
+        (**self).retain()
+    }
+
+    fn release(&mut self) {
+        (**self).release();
+    }
+
+    fn remove(&mut self) {
+        (**self).remove();
+    }
+}
+
+/// Tries to retain every cell. Returns 'true' when all of them are alive.
+pub fn retain_all<'a, C: Retainable + ?Sized + 'a>(
+    cells: impl IntoIterator<Item = &'a mut C>,
+) -> bool {
+    let mut all = true;
+    for cell in cells {
+        all &= cell.retain();
+    }
+    all
+}
+
+/// Releases every cell.
+pub fn release_all<'a, C: Retainable + ?Sized + 'a>(cells: impl IntoIterator<Item = &'a mut C>) {
+    cells.into_iter().for_each(|cell| cell.release());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{release_all, retain_all, Retainable};
+    use crate::{RCell, SmallRCell, Strong};
+
+    #[test]
+    fn homogeneous() {
+        let keep = Strong::new(2);
+        let mut cells = vec![RCell::new(1), RCell::from(keep.clone())];
+        release_all(&mut cells);
+        assert!(!cells[1].retained());
+        assert!(!retain_all(&mut cells));
+        assert!(cells[1].retained());
+        cells.remove(0);
+        assert!(retain_all(cells.iter_mut()));
+    }
+
+    #[test]
+    fn heterogeneous() {
+        let keep = Strong::new("foo");
+        let mut cells: Vec<Box<dyn Retainable>> = vec![
+            Box::new(RCell::from(keep.clone())),
+            Box::new(SmallRCell::new(1u8)),
+        ];
+        release_all(&mut cells);
+        assert!(!cells[0].retained());
+        assert!(!retain_all(&mut cells));
+        assert!(cells[0].retained());
+    }
+}
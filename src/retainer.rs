@@ -0,0 +1,307 @@
+//! Retainers deciding which cells of a set stay strong.
+
+use std::collections::HashMap;
+
+use crate::iter::drain_map;
+use crate::{CostOf, DrainDead, RCell, Strong};
+
+/// Key of an entry in a `PriorityRetainer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RetainerKey(u64);
+
+#[derive(Debug)]
+struct Slot<T, P> {
+    cell: RCell<T>,
+    priority: P,
+}
+
+/// Why a retainer released an entry, passed to eviction listeners.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionReason {
+    /// Other entries took precedence within the capacity.
+    Capacity,
+    /// The retainer was asked to shrink, for example under memory pressure.
+    Shrink,
+    /// The entry was removed.
+    Removed,
+}
+
+/// Listener called with the key, a final strong reference and the reason when a retainer
+/// releases a retained entry.
+pub type EvictionListener<K, T> = Box<dyn FnMut(K, &Strong<T>, EvictionReason) + Send>;
+
+/// Keeps the `capacity` cells with the highest priority strong and releases all others.
+/// Priorities are supplied on insertion and can be updated or recomputed from the values at
+/// runtime. Cells whose value got dropped don't count towards the capacity.
+///
+/// Every change rebalances the whole set, which sorts all entries.
+pub struct PriorityRetainer<T, P: Ord> {
+    capacity: usize,
+    next_key: u64,
+    slots: HashMap<RetainerKey, Slot<T, P>>,
+    listeners: Vec<EvictionListener<RetainerKey, T>>,
+}
+
+impl<T: std::fmt::Debug, P: Ord + std::fmt::Debug> std::fmt::Debug for PriorityRetainer<T, P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PriorityRetainer")
+            .field("capacity", &self.capacity)
+            .field("slots", &self.slots)
+            .field("listeners", &self.listeners.len())
+            .finish()
+    }
+}
+
+impl<T, P: Ord> PriorityRetainer<T, P> {
+    /// Creates a retainer which keeps at most `capacity` cells strong.
+    pub fn new(capacity: usize) -> Self {
+        PriorityRetainer {
+            capacity,
+            next_key: 0,
+            slots: HashMap::new(),
+            listeners: Vec::new(),
+        }
+    }
+
+    /// Adds a listener which is called whenever a retained entry gets released or removed.
+    /// It receives a strong reference to the value, which may be persisted or logged.
+    pub fn add_eviction_listener(
+        &mut self,
+        listener: impl FnMut(RetainerKey, &Strong<T>, EvictionReason) + Send + 'static,
+    ) {
+        self.listeners.push(Box::new(listener));
+    }
+
+    fn notify(
+        listeners: &mut [EvictionListener<RetainerKey, T>],
+        key: RetainerKey,
+        cell: &RCell<T>,
+        reason: EvictionReason,
+    ) {
+        if !cell.retained() || listeners.is_empty() {
+            return;
+        }
+        if let Some(strong) = cell.request() {
+            for listener in listeners.iter_mut() {
+                listener(key, &strong, reason);
+            }
+        }
+    }
+
+    /// Returns the number of entries.
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Returns 'true' when there are no entries.
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// Returns the number of cells which may be retained at once.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Changes the capacity and rebalances.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.rebalance();
+    }
+
+    /// Adds a cell with the given priority.
+    pub fn insert(&mut self, cell: RCell<T>, priority: P) -> RetainerKey {
+        let key = RetainerKey(self.next_key);
+        self.next_key += 1;
+        self.slots.insert(key, Slot { cell, priority });
+        self.rebalance();
+        key
+    }
+
+    /// Removes an entry and returns its cell.
+    pub fn remove(&mut self, key: RetainerKey) -> Option<RCell<T>> {
+        let slot = self.slots.remove(&key)?;
+        Self::notify(
+            &mut self.listeners,
+            key,
+            &slot.cell,
+            EvictionReason::Removed,
+        );
+        self.rebalance();
+        Some(slot.cell)
+    }
+
+    /// Returns the cell of an entry.
+    pub fn get(&self, key: RetainerKey) -> Option<&RCell<T>> {
+        self.slots.get(&key).map(|slot| &slot.cell)
+    }
+
+    /// Tries to get a `Strong<T>` from an entry, see `RCell::request()`.
+    pub fn request(&self, key: RetainerKey) -> Option<Strong<T>> {
+        self.get(key)?.request()
+    }
+
+    /// Returns the priority of an entry.
+    pub fn priority(&self, key: RetainerKey) -> Option<&P> {
+        self.slots.get(&key).map(|slot| &slot.priority)
+    }
+
+    /// Updates the priority of an entry and rebalances. Returns 'false' when there is no
+    /// such entry.
+    pub fn set_priority(&mut self, key: RetainerKey, priority: P) -> bool {
+        match self.slots.get_mut(&key) {
+            Some(slot) => {
+                slot.priority = priority;
+                self.rebalance();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Recomputes the priorities of all entries whose value is alive and rebalances.
+    pub fn recompute(&mut self, mut f: impl FnMut(&T) -> P) {
+        for slot in self.slots.values_mut() {
+            if let Some(strong) = slot.cell.request() {
+                slot.priority = f(&strong);
+            }
+        }
+        self.rebalance();
+    }
+
+    /// Returns the approximate number of bytes held by the retained cells, see `CostOf`.
+    pub fn retained_cost(&self) -> usize
+    where
+        T: CostOf,
+    {
+        self.slots
+            .values()
+            .filter(|slot| slot.cell.retained())
+            .filter_map(|slot| slot.cell.request())
+            .map(|value| value.cost_of())
+            .sum()
+    }
+
+    /// Iterates over all entries.
+    pub fn iter(&self) -> impl Iterator<Item = (RetainerKey, &RCell<T>)> + '_ {
+        self.slots.iter().map(|(key, slot)| (*key, &slot.cell))
+    }
+
+    /// Retains the cells with the highest priority and releases the others.
+    pub fn rebalance(&mut self) {
+        self.retain_top(self.capacity, EvictionReason::Capacity);
+    }
+
+    /// Releases all but the `target` highest priority cells once, without changing the
+    /// capacity. The next rebalance retains the remaining live cells up to the capacity again.
+    pub fn shrink_to(&mut self, target: usize) {
+        self.retain_top(target.min(self.capacity), EvictionReason::Shrink);
+    }
+
+    fn retain_top(&mut self, count: usize, reason: EvictionReason) {
+        let mut slots: Vec<_> = self.slots.iter_mut().collect();
+        slots.sort_by(|(_, a), (_, b)| b.priority.cmp(&a.priority));
+        let mut retained = 0;
+        for (key, slot) in slots {
+            if retained < count && slot.cell.retain().is_some() {
+                retained += 1;
+            } else {
+                Self::notify(&mut self.listeners, *key, &slot.cell, reason);
+                slot.cell.release();
+            }
+        }
+    }
+}
+
+impl<T, P: Ord> DrainDead for PriorityRetainer<T, P> {
+    type Item = (RetainerKey, RCell<T>);
+
+    /// Removes the entries whose value got dropped, no eviction listeners are called.
+    fn drain_dead(&mut self) -> Vec<(RetainerKey, RCell<T>)> {
+        drain_map(&mut self.slots, |slot| slot.cell.refcount() == 0)
+            .into_iter()
+            .map(|(key, slot)| (key, slot.cell))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PriorityRetainer;
+    use crate::RCell;
+
+    #[test]
+    fn top_k() {
+        let mut retainer = PriorityRetainer::new(2);
+        let low = retainer.insert(RCell::new("low"), 1);
+        let high = retainer.insert(RCell::new("high"), 10);
+        let keep = RCell::new("mid");
+        let strong = keep.request().unwrap();
+        let mid = retainer.insert(keep, 5);
+        assert!(retainer.get(high).unwrap().retained());
+        assert!(retainer.get(mid).unwrap().retained());
+        assert_eq!(retainer.request(low), None);
+
+        // 'low' got dropped and does not count
+        retainer.set_priority(mid, 0);
+        assert!(retainer.get(mid).unwrap().retained());
+        retainer.insert(RCell::new("other"), 3);
+        assert!(!retainer.get(mid).unwrap().retained());
+        assert_eq!(*retainer.request(mid).unwrap(), "mid");
+        drop(strong);
+        assert_eq!(retainer.request(mid), None);
+    }
+
+    #[test]
+    fn recompute() {
+        let mut retainer = PriorityRetainer::new(1);
+        let (first, second) = (RCell::new(1), RCell::new(2));
+        let (strong1, strong2) = (first.request().unwrap(), second.request().unwrap());
+        let one = retainer.insert(first, 0);
+        let two = retainer.insert(second, 0);
+        retainer.recompute(|value| *value);
+        assert_eq!(retainer.retained_cost(), 4);
+        assert!(retainer.get(two).unwrap().retained());
+        assert!(!retainer.get(one).unwrap().retained());
+        retainer.recompute(|value| -*value);
+        assert!(retainer.get(one).unwrap().retained());
+        assert!(!retainer.get(two).unwrap().retained());
+        drop((strong1, strong2));
+        assert_eq!(retainer.remove(two).unwrap().request(), None);
+        assert_eq!(retainer.len(), 1);
+
+        use crate::DrainDead;
+        assert!(retainer.drain_dead().is_empty());
+        retainer.set_capacity(0);
+        assert_eq!(retainer.drain_dead().len(), 1);
+        assert!(retainer.is_empty());
+    }
+
+    #[test]
+    fn eviction_listener() {
+        use std::sync::{Arc, Mutex};
+
+        use super::EvictionReason;
+
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let mut retainer = PriorityRetainer::new(1);
+        let log = evicted.clone();
+        retainer.add_eviction_listener(move |_key, value: &crate::Strong<i32>, reason| {
+            log.lock().unwrap().push((**value, reason));
+        });
+        let one = retainer.insert(RCell::new(1), 1);
+        retainer.insert(RCell::new(2), 2);
+        let three = retainer.insert(RCell::new(3), 3);
+        retainer.shrink_to(0);
+        retainer.remove(one);
+        retainer.remove(three);
+        assert_eq!(
+            *evicted.lock().unwrap(),
+            vec![
+                (1, EvictionReason::Capacity),
+                (2, EvictionReason::Capacity),
+                (3, EvictionReason::Shrink),
+            ]
+        );
+    }
+}
@@ -0,0 +1,202 @@
+//! Session store with sliding expiration.
+
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+use crate::{RCell, Strong};
+
+#[derive(Debug)]
+struct Session<T> {
+    cell: RCell<T>,
+    ttl: Duration,
+    expires: Instant,
+}
+
+/// Keeps sessions retained for a time to live which slides forward on every access. Expired
+/// sessions are released by `sweep()` or when accessed, they stay reachable as long as
+/// something still uses them (an in flight request) and get retained again when accessed
+/// then. Sessions which are expired and dropped are removed.
+#[derive(Debug)]
+pub struct SessionStore<K, T> {
+    ttl: Duration,
+    sessions: HashMap<K, Session<T>>,
+}
+
+impl<K: Eq + Hash, T> SessionStore<K, T> {
+    /// Creates a store with `ttl` as default time to live.
+    pub fn new(ttl: Duration) -> Self {
+        SessionStore {
+            ttl,
+            sessions: HashMap::new(),
+        }
+    }
+
+    /// Returns the number of sessions, including expired ones not yet swept.
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// Returns 'true' when there are no sessions.
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+
+    /// Returns the default time to live.
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Adds a session with the default time to live.
+    pub fn insert(&mut self, key: K, value: T) -> Strong<T> {
+        self.insert_with_ttl(key, value, self.ttl)
+    }
+
+    /// Adds a session with its own time to live.
+    pub fn insert_with_ttl(&mut self, key: K, value: T, ttl: Duration) -> Strong<T> {
+        let strong = Strong::new(value);
+        self.sessions.insert(
+            key,
+            Session {
+                cell: RCell::from(strong.clone()),
+                ttl,
+                expires: Instant::now() + ttl,
+            },
+        );
+        strong
+    }
+
+    /// Returns a session when it is alive and extends its lifetime. An expired session is
+    /// only returned when it is still in use elsewhere.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<Strong<T>>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let session = self.sessions.get_mut(key)?;
+        if session.expires <= Instant::now() {
+            session.cell.release();
+        }
+        match session.cell.retain() {
+            Some(strong) => {
+                session.expires = Instant::now() + session.ttl;
+                Some(strong)
+            }
+            None => {
+                self.sessions.remove(key);
+                None
+            }
+        }
+    }
+
+    /// Returns the time left until a session expires, zero when it already expired.
+    pub fn remaining<Q>(&self, key: &Q) -> Option<Duration>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let session = self.sessions.get(key)?;
+        Some(session.expires.saturating_duration_since(Instant::now()))
+    }
+
+    /// Removes a session, returns it when alive.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<Strong<T>>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.sessions.remove(key)?.cell.request()
+    }
+
+    /// Releases the expired sessions and removes those which got dropped. Returns the number
+    /// of sessions removed.
+    pub fn sweep(&mut self) -> usize {
+        let now = Instant::now();
+        let before = self.sessions.len();
+        self.sessions.retain(|_, session| {
+            if session.expires <= now {
+                session.cell.release();
+            }
+            session.cell.refcount() > 0
+        });
+        before - self.sessions.len()
+    }
+}
+
+#[cfg(feature = "sync")]
+impl<K: Eq + Hash + Send + 'static, T: Send + Sync + 'static> SessionStore<K, T> {
+    /// Starts a thread calling `sweep()` every `interval`. The thread ends when the last
+    /// reference to the store is dropped.
+    pub fn spawn_sweeper(
+        store: &std::sync::Arc<std::sync::Mutex<Self>>,
+        interval: Duration,
+    ) -> std::thread::JoinHandle<()> {
+        let store = std::sync::Arc::downgrade(store);
+        std::thread::spawn(move || {
+            while let Some(store) = store.upgrade() {
+                store
+                    .lock()
+                    .unwrap_or_else(|poison| poison.into_inner())
+                    .sweep();
+                drop(store);
+                std::thread::sleep(interval);
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::SessionStore;
+
+    #[test]
+    fn sliding_expiration() {
+        let mut store = SessionStore::new(Duration::from_millis(500));
+        store.insert("idle", 1);
+        let in_use = store.insert("in use", 2);
+        store.insert_with_ttl("long", 3, Duration::from_secs(60));
+        assert!(store.remaining("long").unwrap() > Duration::from_secs(1));
+
+        std::thread::sleep(Duration::from_millis(600));
+        assert_eq!(store.remaining("idle"), Some(Duration::ZERO));
+        // expired and not swept yet
+        assert_eq!(store.get("idle"), None);
+        assert_eq!(store.len(), 2);
+
+        // expired but still in use, accessing it retains it again
+        assert_eq!(store.get("in use"), Some(in_use.clone()));
+        drop(in_use);
+        assert!(store.remaining("in use").unwrap() > Duration::ZERO);
+        assert_eq!(store.sweep(), 0);
+        assert_eq!(*store.remove("long").unwrap(), 3);
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn sweep() {
+        let mut store = SessionStore::new(Duration::ZERO);
+        store.insert("idle", 1);
+        let in_use = store.insert("in use", 2);
+        assert_eq!(store.sweep(), 1);
+        assert_eq!(store.get("in use"), Some(in_use));
+        assert_eq!(store.len(), 1);
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn sweeper() {
+        use std::sync::{Arc, Mutex};
+
+        let store = Arc::new(Mutex::new(SessionStore::new(Duration::ZERO)));
+        store.lock().unwrap().insert(1, "session");
+        let thread = SessionStore::spawn_sweeper(&store, Duration::from_millis(5));
+        while !store.lock().unwrap().is_empty() {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        drop(store);
+        thread.join().unwrap();
+    }
+}
@@ -0,0 +1,677 @@
+//! Lock striped variant of `RCellCache`.
+
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::{CachePolicy, CostOf, EntryInfo, Lru, RCellCache, Strong};
+
+#[derive(Debug)]
+enum FlightState<V> {
+    Loading,
+    Done(Option<Strong<V>>),
+}
+
+/// A loader in progress, other threads missing on the same key wait for its result.
+#[derive(Debug)]
+struct Flight<V> {
+    state: Mutex<FlightState<V>>,
+    done: Condvar,
+}
+
+impl<V> Flight<V> {
+    fn new() -> Self {
+        Flight {
+            state: Mutex::new(FlightState::Loading),
+            done: Condvar::new(),
+        }
+    }
+
+    /// Waits for the loader, returns 'None' when it panicked.
+    fn wait(&self) -> Option<Strong<V>> {
+        let mut state = self
+            .state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        loop {
+            match &*state {
+                FlightState::Loading => {
+                    state = self
+                        .done
+                        .wait(state)
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                }
+                FlightState::Done(result) => return result.clone(),
+            }
+        }
+    }
+}
+
+/// Completes a flight when dropped, also when the loader panics or fails. Without a result
+/// the waiting threads try again.
+struct FlightGuard<'a, K: Eq + Hash, V, P: CachePolicy, E> {
+    cache: &'a ShardedRCellCache<K, V, P, E>,
+    key: &'a K,
+    flight: Arc<Flight<V>>,
+    result: Option<Strong<V>>,
+}
+
+impl<K: Eq + Hash, V, P: CachePolicy, E> Drop for FlightGuard<'_, K, V, P, E> {
+    fn drop(&mut self) {
+        let mut shard = self.cache.shard(self.key);
+        if shard
+            .flights
+            .get(self.key)
+            .is_some_and(|flight| Arc::ptr_eq(flight, &self.flight))
+        {
+            shard.flights.remove(self.key);
+        }
+        drop(shard);
+        *self
+            .flight
+            .state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) =
+            FlightState::Done(self.result.take());
+        self.flight.done.notify_all();
+    }
+}
+
+#[derive(Debug)]
+struct Shard<K, V, P, E> {
+    cache: RCellCache<K, V, P, E>,
+    flights: HashMap<K, Arc<Flight<V>>>,
+}
+
+type LockedShard<K, V, P, E> = Mutex<Shard<K, V, P, E>>;
+
+/// Outcome of looking a key up in a shard for the loading methods.
+enum Lookup<V> {
+    Found(Strong<V>),
+    Wait(Arc<Flight<V>>),
+    Load(Arc<Flight<V>>),
+}
+
+impl<K: Eq + Hash + Clone, V, P: CachePolicy, E> Shard<K, V, P, E> {
+    /// Returns the value, the flight of another thread loading it or registers a new flight
+    /// for the caller to load it.
+    fn lookup(&mut self, key: &K) -> Lookup<V> {
+        if let Some(strong) = self.cache.get(key) {
+            return Lookup::Found(strong);
+        }
+        if let Some(flight) = self.flights.get(key) {
+            return Lookup::Wait(flight.clone());
+        }
+        let flight = Arc::new(Flight::new());
+        self.flights.insert(key.clone(), flight.clone());
+        Lookup::Load(flight)
+    }
+}
+
+/// A `RCellCache` split into shards which are locked independently, accesses to keys in
+/// different shards don't contend. Each shard retains at most its share of the capacity,
+/// the policy applies per shard.
+///
+/// The loaders of `get_or_insert_with()` and `try_get_or_insert_with()` run without holding
+/// the shard lock. Concurrent misses on the same key run the loader only once, the other
+/// threads wait for its result.
+///
+/// Operations spanning all entries, like `warm_keys()` or `for_each_info()`, lock one shard
+/// after the other, they don't see a consistent state of the whole cache.
+#[derive(Debug)]
+pub struct ShardedRCellCache<K, V, P = Lru, E = ()> {
+    hasher: RandomState,
+    shards: Box<[LockedShard<K, V, P, E>]>,
+}
+
+/// Returns the capacity of shard `index`, the remainder is spread over the first shards.
+fn share(capacity: usize, shards: usize, index: usize) -> usize {
+    capacity / shards + usize::from(index < capacity % shards)
+}
+
+impl<K: Eq + Hash, V> ShardedRCellCache<K, V> {
+    /// Creates a LRU cache with `shards` shards retaining at most `capacity` values overall.
+    pub fn new(capacity: usize, shards: usize) -> Self {
+        Self::with_policy(capacity, shards, Lru)
+    }
+}
+
+impl<K: Eq + Hash, V, P: CachePolicy + Clone> ShardedRCellCache<K, V, P> {
+    /// Creates a cache with `shards` shards retaining at most `capacity` values overall,
+    /// selected by `policy`.
+    ///
+    /// # Panics
+    ///
+    /// When `shards` is zero.
+    pub fn with_policy(capacity: usize, shards: usize, policy: P) -> Self {
+        assert!(shards > 0, "ShardedRCellCache needs at least one shard");
+        ShardedRCellCache {
+            hasher: RandomState::new(),
+            shards: (0..shards)
+                .map(|index| {
+                    Mutex::new(Shard {
+                        cache: RCellCache::with_policy(
+                            share(capacity, shards, index),
+                            policy.clone(),
+                        ),
+                        flights: HashMap::new(),
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+impl<K: Eq + Hash, V, P: CachePolicy, E> ShardedRCellCache<K, V, P, E> {
+    fn lock(shard: &LockedShard<K, V, P, E>) -> MutexGuard<'_, Shard<K, V, P, E>> {
+        shard
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn shard<Q: Hash + ?Sized>(&self, key: &Q) -> MutexGuard<'_, Shard<K, V, P, E>> {
+        let index = self.hasher.hash_one(key) as usize % self.shards.len();
+        Self::lock(&self.shards[index])
+    }
+
+    fn shards(&self) -> impl Iterator<Item = MutexGuard<'_, Shard<K, V, P, E>>> {
+        self.shards.iter().map(Self::lock)
+    }
+
+    /// Changes the error type of the loaders passed to `try_get_or_insert_with()`, see
+    /// `RCellCache::with_error()`.
+    pub fn with_error<F>(self) -> ShardedRCellCache<K, V, P, F> {
+        ShardedRCellCache {
+            hasher: self.hasher,
+            shards: self
+                .shards
+                .into_vec()
+                .into_iter()
+                .map(|shard| {
+                    let shard = shard
+                        .into_inner()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner());
+                    Mutex::new(Shard {
+                        cache: shard.cache.with_error(),
+                        flights: shard.flights,
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the number of shards.
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Returns the number of entries, including released ones whose value may be gone.
+    pub fn len(&self) -> usize {
+        self.shards().map(|shard| shard.cache.len()).sum()
+    }
+
+    /// Returns 'true' when there are no entries.
+    pub fn is_empty(&self) -> bool {
+        self.shards().all(|shard| shard.cache.is_empty())
+    }
+
+    /// Returns the number of retained values.
+    pub fn retained(&self) -> usize {
+        self.shards().map(|shard| shard.cache.retained()).sum()
+    }
+
+    /// Returns the approximate number of bytes held by the retained values, see `CostOf`.
+    pub fn retained_cost(&self) -> usize
+    where
+        V: CostOf,
+    {
+        self.shards().map(|shard| shard.cache.retained_cost()).sum()
+    }
+
+    /// Returns the number of values which may be retained at once.
+    pub fn capacity(&self) -> usize {
+        self.shards().map(|shard| shard.cache.capacity()).sum()
+    }
+
+    /// Changes the overall capacity, releases surplus entries.
+    pub fn set_capacity(&self, capacity: usize) {
+        let shards = self.shards.len();
+        self.shards().enumerate().for_each(|(index, mut shard)| {
+            shard.cache.set_capacity(share(capacity, shards, index))
+        });
+    }
+
+    /// Returns the value for `key` and marks it as used, see `RCellCache::get()`.
+    pub fn get<Q>(&self, key: &Q) -> Option<Strong<V>>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.shard(key).cache.get(key)
+    }
+
+    /// Returns the value for `key` without marking it as used or retaining it.
+    pub fn peek<Q>(&self, key: &Q) -> Option<Strong<V>>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.shard(key).cache.peek(key)
+    }
+
+    /// Returns 'true' when there is an entry for `key` whose value is alive.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.shard(key).cache.contains_key(key)
+    }
+
+    /// Inserts a value, replacing any previous entry for `key`.
+    pub fn insert(&self, key: K, value: V) -> Strong<V> {
+        self.shard(&key).cache.insert(key, value)
+    }
+
+    /// Returns the value for `key`, calls `loader` and inserts its result when there is none.
+    /// When other threads miss on the same key meanwhile they wait for this loader instead of
+    /// running their own. Should the loader panic, one of the waiting threads runs its loader.
+    pub fn get_or_insert_with(&self, key: K, loader: impl FnOnce() -> V) -> Strong<V>
+    where
+        K: Clone,
+    {
+        loop {
+            // the shard must be unlocked before waiting
+            let lookup = self.shard(&key).lookup(&key);
+            let flight = match lookup {
+                Lookup::Found(strong) => return strong,
+                Lookup::Wait(flight) => match flight.wait() {
+                    Some(strong) => return strong,
+                    None => continue,
+                },
+                Lookup::Load(flight) => flight,
+            };
+            let mut guard = FlightGuard {
+                cache: self,
+                key: &key,
+                flight,
+                result: None,
+            };
+            let value = loader();
+            let strong = self.shard(&key).cache.insert(key.clone(), value);
+            guard.result = Some(strong.clone());
+            return strong;
+        }
+    }
+
+    /// Returns how long loader failures are cached, see `RCellCache::negative_ttl()`.
+    pub fn negative_ttl(&self) -> Option<Duration> {
+        Self::lock(&self.shards[0]).cache.negative_ttl()
+    }
+
+    /// Enables caching of loader failures for `ttl` in every shard, see
+    /// `RCellCache::set_negative_ttl()`. Each shard keeps at most as many failures as its
+    /// share of the capacity.
+    pub fn set_negative_ttl(&self, ttl: Option<Duration>) {
+        self.shards()
+            .for_each(|mut shard| shard.cache.set_negative_ttl(ttl));
+    }
+
+    /// Like `get_or_insert_with()` but the loader may fail, see
+    /// `RCellCache::try_get_or_insert_with()`. A cached failure is returned without calling
+    /// the loader. When the loader fails the threads waiting for it try again, they get the
+    /// cached failure when negative caching is enabled.
+    pub fn try_get_or_insert_with(
+        &self,
+        key: K,
+        loader: impl FnOnce() -> Result<V, E>,
+    ) -> Result<Strong<V>, E>
+    where
+        K: Clone,
+        E: Clone,
+    {
+        loop {
+            let flight = {
+                let mut shard = self.shard(&key);
+                if let Some(error) = shard.cache.failure(&key) {
+                    return Err(error);
+                }
+                match shard.lookup(&key) {
+                    Lookup::Found(strong) => return Ok(strong),
+                    Lookup::Wait(flight) => {
+                        drop(shard);
+                        match flight.wait() {
+                            Some(strong) => return Ok(strong),
+                            None => continue,
+                        }
+                    }
+                    Lookup::Load(flight) => flight,
+                }
+            };
+            let mut guard = FlightGuard {
+                cache: self,
+                key: &key,
+                flight,
+                result: None,
+            };
+            let result = loader();
+            let mut shard = self.shard(&key);
+            return match result {
+                Ok(value) => {
+                    let strong = shard.cache.insert(key.clone(), value);
+                    drop(shard);
+                    guard.result = Some(strong.clone());
+                    Ok(strong)
+                }
+                Err(error) => {
+                    shard.cache.fail(key.clone(), error.clone());
+                    drop(shard);
+                    Err(error)
+                }
+            };
+        }
+    }
+
+    /// Returns the entry for `key`, see `RCellCache::entry()`. The shard of the key stays
+    /// locked until the entry is dropped, loaders passed to it run under that lock.
+    pub fn entry(&self, key: K) -> ShardedCacheEntry<'_, K, V, P, E> {
+        ShardedCacheEntry {
+            shard: self.shard(&key),
+            key,
+        }
+    }
+
+    /// Removes the entry for `key`, returns its value when alive.
+    pub fn remove<Q>(&self, key: &Q) -> Option<Strong<V>>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.shard(key).cache.remove(key)
+    }
+
+    /// Returns a human readable listing of all entries, see `RCellCache::dump()`.
+    pub fn dump(&self) -> String
+    where
+        K: std::fmt::Debug,
+    {
+        self.shards().map(|shard| shard.cache.dump()).collect()
+    }
+
+    /// Removes all entries.
+    pub fn clear(&self) {
+        self.shards().for_each(|mut shard| shard.cache.clear());
+    }
+
+    /// Removes the released entries whose value got dropped, returns how many were removed.
+    pub fn purge(&self) -> usize {
+        self.shards().map(|mut shard| shard.cache.purge()).sum()
+    }
+
+    /// Retains the entries matching `f` and releases the others, shard by shard, see
+    /// `RCellCache::retain_where()`.
+    pub fn retain_where(&self, mut f: impl FnMut(&K, &V) -> bool) -> usize {
+        self.shards()
+            .map(|mut shard| shard.cache.retain_where(&mut f))
+            .sum()
+    }
+
+    /// Releases all entries.
+    pub fn release_all(&self) {
+        self.shards()
+            .for_each(|mut shard| shard.cache.release_all());
+    }
+
+    /// Retains and marks as used the entries for all `keys`, see `RCellCache::retain_keys()`.
+    /// Returns the number of entries found alive.
+    pub fn retain_keys<'a, Q>(&self, keys: impl IntoIterator<Item = &'a Q>) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized + 'a,
+    {
+        keys.into_iter()
+            .filter(|key| self.get(*key).is_some())
+            .count()
+    }
+
+    /// Calls `f` with diagnostic information of all entries, shard by shard, see
+    /// `RCellCache::iter_info()`. The information borrows from the locked shard and can't be
+    /// returned as an iterator, `f` must not access this cache.
+    pub fn for_each_info(&self, mut f: impl FnMut(EntryInfo<'_, K, V>)) {
+        for shard in self.shards() {
+            shard.cache.iter_info().for_each(&mut f);
+        }
+    }
+
+    /// Returns the keys of the retained entries, see `RCellCache::warm_keys()`. The keys come
+    /// shard by shard, the policy order only holds within a shard.
+    pub fn warm_keys(&self) -> Vec<K>
+    where
+        K: Clone,
+    {
+        self.shards()
+            .flat_map(|shard| shard.cache.warm_keys())
+            .collect()
+    }
+
+    /// Like `warm_keys()` but returns the values as well, see `RCellCache::warm_entries()`.
+    pub fn warm_entries(&self) -> Vec<(K, Strong<V>)>
+    where
+        K: Clone,
+    {
+        self.shards()
+            .flat_map(|shard| shard.cache.warm_entries())
+            .collect()
+    }
+
+    /// Inserts the values for `keys` as returned by `warm_keys()`, see
+    /// `RCellCache::warm_up()`. Returns the number of inserted entries.
+    pub fn warm_up(
+        &self,
+        keys: impl IntoIterator<Item = K>,
+        mut loader: impl FnMut(&K) -> Option<V>,
+    ) -> usize {
+        let mut inserted = 0;
+        for key in keys {
+            if let Some(value) = loader(&key) {
+                self.insert(key, value);
+                inserted += 1;
+            }
+        }
+        inserted
+    }
+}
+
+/// An entry of a `ShardedRCellCache` as returned by `ShardedRCellCache::entry()`, holding
+/// the lock of its shard. See `CacheEntry` for the operations.
+#[derive(Debug)]
+pub struct ShardedCacheEntry<'a, K, V, P, E = ()> {
+    shard: MutexGuard<'a, Shard<K, V, P, E>>,
+    key: K,
+}
+
+impl<K: Eq + Hash, V, P: CachePolicy, E> ShardedCacheEntry<'_, K, V, P, E> {
+    /// Returns the key of this entry.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Returns the value and marks it as used, see `CacheEntry::or_retain()`.
+    pub fn or_retain(mut self) -> Option<Strong<V>> {
+        self.shard.cache.get(&self.key)
+    }
+
+    /// Returns the value, calls `f` and inserts its result when there is no live value, see
+    /// `CacheEntry::or_insert_with()`.
+    pub fn or_insert_with(self, f: impl FnOnce() -> V) -> Strong<V> {
+        let ShardedCacheEntry { mut shard, key } = self;
+        shard.cache.entry(key).or_insert_with(f)
+    }
+
+    /// Replaces a live value with the one computed by `f` from it, see
+    /// `CacheEntry::and_modify()`.
+    pub fn and_modify(self, f: impl FnOnce(&V) -> V) -> Self {
+        let ShardedCacheEntry { mut shard, key } = self;
+        let key = shard.cache.entry(key).and_modify(f).into_key();
+        ShardedCacheEntry { shard, key }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ShardedRCellCache;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn sharded() {
+        // every shard can retain all entries
+        let cache = ShardedRCellCache::new(16, 4);
+        assert_eq!(cache.shard_count(), 4);
+        assert_eq!(cache.capacity(), 16);
+        assert_eq!(ShardedRCellCache::<u8, u8>::new(10, 4).capacity(), 10);
+        for i in 0..4 {
+            cache.insert(i, i * 10);
+        }
+        assert_eq!(*cache.get(&2).unwrap(), 20);
+        assert_eq!(cache.retained_cost(), 16);
+        assert_eq!(cache.len(), 4);
+        assert_eq!(cache.dump().lines().count(), 4);
+        assert_eq!(*cache.remove(&2).unwrap(), 20);
+        assert!(!cache.contains_key(&2));
+        cache.set_capacity(6);
+        assert_eq!(cache.capacity(), 6);
+        cache.set_capacity(0);
+        assert_eq!(cache.retained(), 0);
+        assert_eq!(cache.purge(), 3);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn try_get_or_insert_with() {
+        let cache = ShardedRCellCache::new(4, 2).with_error::<&str>();
+        cache.set_negative_ttl(Some(Duration::from_secs(60)));
+        assert_eq!(cache.negative_ttl(), Some(Duration::from_secs(60)));
+        assert_eq!(cache.try_get_or_insert_with(1, || Err("down")), Err("down"));
+        // the failure is cached, the loader doesn't run
+        assert_eq!(
+            cache.try_get_or_insert_with(1, || unreachable!()),
+            Err("down")
+        );
+        assert_eq!(*cache.try_get_or_insert_with(2, || Ok(20)).unwrap(), 20);
+        cache.insert(1, 10);
+        assert_eq!(
+            *cache.try_get_or_insert_with(1, || Err("down")).unwrap(),
+            10
+        );
+    }
+
+    #[test]
+    fn entry() {
+        let cache = ShardedRCellCache::new(4, 2);
+        assert_eq!(cache.entry("a").or_retain(), None);
+        assert_eq!(*cache.entry("a").or_insert_with(|| 1), 1);
+        let a = cache.entry("a").and_modify(|value| value + 10);
+        assert_eq!(a.key(), &"a");
+        assert_eq!(a.or_retain().as_deref(), Some(&11));
+    }
+
+    #[test]
+    fn for_each_info() {
+        let cache = ShardedRCellCache::new(6, 2);
+        let kept = [1, 2, 3].map(|i| cache.insert(i, i));
+        let mut keys = Vec::new();
+        cache.for_each_info(|info| keys.push((*info.key, info.counts().strong)));
+        keys.sort();
+        assert_eq!(keys, [(1, 2), (2, 2), (3, 2)]);
+        drop(kept);
+    }
+
+    #[test]
+    fn retain_keys() {
+        let cache = ShardedRCellCache::new(8, 4);
+        let kept = [0, 1, 2, 3].map(|i| cache.insert(i, i));
+        cache.release_all();
+        assert_eq!(cache.retained(), 0);
+        assert_eq!(cache.retain_keys(&[1, 3, 5]), 2);
+        assert_eq!(cache.retained(), 2);
+        drop(kept);
+    }
+
+    #[test]
+    fn warm() {
+        let cache = ShardedRCellCache::new(8, 4);
+        let kept = [0, 1, 2, 3].map(|i| cache.insert(i, i));
+        cache.release_all();
+        cache.get(&1);
+        cache.get(&3);
+        let mut keys = cache.warm_keys();
+        keys.sort();
+        assert_eq!(keys, [1, 3]);
+        assert_eq!(cache.warm_entries().len(), 2);
+        let warm = ShardedRCellCache::new(8, 2);
+        assert_eq!(warm.warm_up(keys, |key| (*key != 3).then_some(key * 10)), 1);
+        assert_eq!(warm.peek(&1).as_deref(), Some(&10));
+        drop(kept);
+    }
+
+    #[test]
+    fn concurrent() {
+        let cache = Arc::new(ShardedRCellCache::new(64, 8));
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let cache = cache.clone();
+                std::thread::spawn(move || {
+                    for i in 0..32 {
+                        assert_eq!(*cache.get_or_insert_with(i, || i * 2), i * 2);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(cache.len(), 32);
+    }
+
+    #[test]
+    fn singleflight() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Barrier;
+
+        let cache = Arc::new(ShardedRCellCache::new(4, 2));
+        let loads = Arc::new(AtomicUsize::new(0));
+        let barrier = Arc::new(Barrier::new(8));
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let (cache, loads, barrier) = (cache.clone(), loads.clone(), barrier.clone());
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    let value = cache.get_or_insert_with("key", || {
+                        loads.fetch_add(1, Ordering::SeqCst);
+                        std::thread::sleep(std::time::Duration::from_millis(50));
+                        42
+                    });
+                    assert_eq!(*value, 42);
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn singleflight_panic() {
+        let cache = ShardedRCellCache::new(4, 1);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            cache.get_or_insert_with(1, || panic!("loader failed"))
+        }));
+        assert!(result.is_err());
+        assert_eq!(*cache.get_or_insert_with(1, || 2), 2);
+    }
+}
@@ -0,0 +1,152 @@
+//! RCell variant storing small values inline.
+
+use crate::{RCell, Strong, Weak};
+
+/// A cell for small `Copy` values which are stored inline without a heap allocation. Only when
+/// a `Strong<T>` or `Weak<T>` is requested the value is moved into a reference counted
+/// allocation, from then on it behaves like a `RCell<T>`.
+#[derive(Debug)]
+pub struct SmallRCell<T: Copy>(Small<T>);
+
+#[derive(Debug)]
+enum Small<T> {
+    Inline(T),
+    Shared(RCell<T>),
+}
+
+impl<T: Copy> SmallRCell<T> {
+    /// Creates a new SmallRCell storing `value` inline.
+    pub fn new(value: T) -> Self {
+        SmallRCell(Small::Inline(value))
+    }
+
+    /// Returns 'true' when the value is still stored inline.
+    pub fn is_inline(&self) -> bool {
+        matches!(self.0, Small::Inline(_))
+    }
+
+    /// Returns 'true' when the value is stored inline or the cell contains a `Strong<T>`.
+    pub fn retained(&self) -> bool {
+        match &self.0 {
+            Small::Inline(_) => true,
+            Small::Shared(cell) => cell.retained(),
+        }
+    }
+
+    /// Returns a copy of the value when it is alive.
+    pub fn get(&self) -> Option<T> {
+        match &self.0 {
+            Small::Inline(value) => Some(*value),
+            Small::Shared(cell) => cell.request().map(|strong| *strong),
+        }
+    }
+
+    /// Moves an inline value into a strong allocation, returns the inner RCell.
+    fn shared(&mut self) -> &mut RCell<T> {
+        if let Small::Inline(value) = self.0 {
+            self.0 = Small::Shared(RCell::new(value));
+        }
+        match &mut self.0 {
+            Small::Shared(cell) => cell,
+            Small::Inline(_) => unreachable!(),
+        }
+    }
+
+    /// Tries to get a `Strong<T>`, an inline value gets moved into a strong allocation.
+    pub fn request(&mut self) -> Option<Strong<T>> {
+        self.shared().request()
+    }
+
+    /// Returns a `Weak<T>` to the value when it is alive, an inline value gets moved into a
+    /// strong allocation.
+    pub fn downgrade(&mut self) -> Option<Weak<T>> {
+        self.shared()
+            .request()
+            .map(|strong| Strong::downgrade(&strong))
+    }
+
+    /// Tries to upgrade this cell to a `Strong<T>`, see `RCell::retain()`. Like `request()`
+    /// an inline value gets moved into a strong allocation.
+    pub fn retain(&mut self) -> Option<Strong<T>> {
+        self.shared().retain()
+    }
+
+    /// Downgrades the cell, see `RCell::release()`. An inline value has no other references
+    /// and gets dropped.
+    pub fn release(&mut self) {
+        match &mut self.0 {
+            Small::Inline(_) => self.0 = Small::Shared(RCell::default()),
+            Small::Shared(cell) => cell.release(),
+        }
+    }
+
+    /// Removes the value from the cell, see `RCell::remove()`.
+    pub fn remove(&mut self) {
+        self.0 = Small::Shared(RCell::default());
+    }
+}
+
+impl<T: Copy> From<T> for SmallRCell<T> {
+    /// Creates a new SmallRCell storing `value` inline.
+    fn from(value: T) -> Self {
+        SmallRCell::new(value)
+    }
+}
+
+impl<T: Copy> Default for SmallRCell<T> {
+    /// Creates a SmallRCell that doesn't hold any value.
+    fn default() -> Self {
+        SmallRCell(Small::Shared(RCell::default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::SmallRCell;
+
+    #[test]
+    fn inline() {
+        let mut cell = SmallRCell::new(42u32);
+        assert!(cell.is_inline());
+        assert!(cell.retained());
+        assert_eq!(cell.get(), Some(42));
+        cell.release();
+        assert_eq!(cell.get(), None);
+    }
+
+    #[test]
+    fn promote() {
+        let mut cell = SmallRCell::new(42u32);
+        let weak = cell.downgrade().unwrap();
+        assert!(!cell.is_inline());
+        assert_eq!(*weak.upgrade().unwrap(), 42);
+        let strong = cell.request().unwrap();
+        cell.release();
+        assert!(!cell.retained());
+        assert_eq!(cell.get(), Some(42));
+        drop(strong);
+        assert_eq!(cell.get(), None);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn retain() {
+        let mut cell = SmallRCell::new(42u32);
+        let strong = cell.retain().unwrap();
+        assert_eq!(*strong, 42);
+        assert!(!cell.is_inline());
+        assert!(cell.retained());
+        cell.release();
+        assert!(crate::Strong::ptr_eq(&cell.retain().unwrap(), &strong));
+        drop(strong);
+        cell.release();
+        assert_eq!(cell.retain(), None);
+    }
+
+    #[test]
+    fn default() {
+        let mut cell = SmallRCell::<u8>::default();
+        assert!(!cell.retained());
+        assert_eq!(cell.request(), None);
+    }
+}
@@ -0,0 +1,67 @@
+//! Global operation counters.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub(crate) static UPGRADES: AtomicU64 = AtomicU64::new(0);
+pub(crate) static FAILED_UPGRADES: AtomicU64 = AtomicU64::new(0);
+pub(crate) static RETAINS: AtomicU64 = AtomicU64::new(0);
+pub(crate) static RELEASES: AtomicU64 = AtomicU64::new(0);
+pub(crate) static REMOVES: AtomicU64 = AtomicU64::new(0);
+
+pub(crate) fn bump(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Snapshot of the global operation counters as returned by `stats()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Stats {
+    /// Weak references successfully upgraded by `request()` or `retain()`.
+    pub upgrades: u64,
+    /// Weak references which could not be upgraded because the value was dropped.
+    pub failed_upgrades: u64,
+    /// Successful `retain()` calls.
+    pub retains: u64,
+    /// Strong references given up by `release()` or `split()`, releasing a cell which was
+    /// not retained doesn't count.
+    pub releases: u64,
+    /// `remove()` calls.
+    pub removes: u64,
+}
+
+/// Returns the global operation counters over all RCells. The counters are read one by one
+/// while other threads may continue to update them.
+pub fn stats() -> Stats {
+    Stats {
+        upgrades: UPGRADES.load(Ordering::Relaxed),
+        failed_upgrades: FAILED_UPGRADES.load(Ordering::Relaxed),
+        retains: RETAINS.load(Ordering::Relaxed),
+        releases: RELEASES.load(Ordering::Relaxed),
+        removes: REMOVES.load(Ordering::Relaxed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::stats;
+    use crate::{RCell, Strong};
+
+    #[test]
+    fn count() {
+        let before = stats();
+        let strong = Strong::new(1);
+        let mut rcell = RCell::from(Strong::downgrade(&strong));
+        rcell.request();
+        rcell.retain();
+        rcell.release();
+        drop(strong);
+        rcell.request();
+        rcell.remove();
+        let after = stats();
+        // other tests run concurrently
+        assert!(after.upgrades >= before.upgrades + 2);
+        assert!(after.failed_upgrades > before.failed_upgrades);
+        assert!(after.retains > before.retains);
+        assert!(after.releases > before.releases);
+        assert!(after.removes > before.removes);
+    }
+}
@@ -0,0 +1,111 @@
+//! Cell which is either strong or empty.
+
+use crate::{RCell, Strong};
+
+/// A cell that only ever holds a `Strong<T>` or nothing, it has no weak state and never lets
+/// a value go by itself. The layout is the same as `Option<Strong<T>>`. Use it where eviction
+/// is never wanted or together with reference counting backends without weak references.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct StrongCell<T: ?Sized>(Option<Strong<T>>);
+
+impl<T> StrongCell<T> {
+    /// Creates a StrongCell holding `value`.
+    pub fn new(value: T) -> Self {
+        StrongCell(Some(Strong::new(value)))
+    }
+}
+
+impl<T: ?Sized> StrongCell<T> {
+    /// Creates an empty StrongCell.
+    pub const fn empty() -> Self {
+        StrongCell(None)
+    }
+
+    /// Returns 'true' when the cell holds no value.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_none()
+    }
+
+    /// Returns a `Strong<T>` to the value.
+    pub fn request(&self) -> Option<Strong<T>> {
+        self.0.clone()
+    }
+
+    /// Stores `strong` in the cell, returns the previous value.
+    pub fn replace(&mut self, strong: Strong<T>) -> Option<Strong<T>> {
+        self.0.replace(strong)
+    }
+
+    /// Takes the value out, leaving the cell empty.
+    pub fn take(&mut self) -> Option<Strong<T>> {
+        self.0.take()
+    }
+
+    /// Drops the reference to the value.
+    pub fn remove(&mut self) {
+        self.0 = None;
+    }
+
+    /// Returns the content as `Option<Strong<T>>`.
+    pub fn as_option(&self) -> &Option<Strong<T>> {
+        &self.0
+    }
+
+    /// Unwraps the content.
+    pub fn into_option(self) -> Option<Strong<T>> {
+        self.0
+    }
+}
+
+impl<T: ?Sized> Default for StrongCell<T> {
+    /// Creates an empty StrongCell.
+    fn default() -> Self {
+        StrongCell::empty()
+    }
+}
+
+impl<T: ?Sized> From<Strong<T>> for StrongCell<T> {
+    fn from(strong: Strong<T>) -> Self {
+        StrongCell(Some(strong))
+    }
+}
+
+impl<T: ?Sized> From<Option<Strong<T>>> for StrongCell<T> {
+    fn from(option: Option<Strong<T>>) -> Self {
+        StrongCell(option)
+    }
+}
+
+impl<T: ?Sized> From<StrongCell<T>> for RCell<T> {
+    /// Creates a strong RCell from a full StrongCell, an empty RCell otherwise.
+    fn from(cell: StrongCell<T>) -> Self {
+        match cell.0 {
+            Some(strong) => RCell::from(strong),
+            None => RCell::empty(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StrongCell;
+    use crate::{RCell, Strong};
+
+    #[test]
+    fn strong_or_empty() {
+        let mut cell = StrongCell::new(1);
+        let strong = cell.request().unwrap();
+        assert_eq!(cell.replace(Strong::new(2)), Some(strong));
+        assert_eq!(*cell.take().unwrap(), 2);
+        assert!(cell.is_empty());
+        assert_eq!(cell, StrongCell::default());
+
+        let rcell = RCell::from(StrongCell::<str>::from(Strong::from("foo")));
+        assert!(rcell.retained());
+        assert_eq!(
+            std::mem::size_of::<StrongCell<u8>>(),
+            std::mem::size_of::<Option<Strong<u8>>>()
+        );
+    }
+}
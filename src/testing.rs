@@ -0,0 +1,159 @@
+//! Test doubles for code using RCells.
+//!
+//! ```
+//! use rcell::testing::{MockRCell, Script};
+//!
+//! // the cache miss path of the code under test runs on the third request
+//! let cell = MockRCell::new(1, Script::FailAfter(2));
+//! assert!(cell.request().is_some());
+//! assert!(cell.request().is_some());
+//! assert!(cell.request().is_none());
+//! ```
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{RCell, Retainable, Strong};
+
+/// Scripted outcome of `MockRCell::request()` and `MockRCell::retain()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Script {
+    /// Behave like the wrapped RCell.
+    Pass,
+    /// The first `n` calls behave like the wrapped RCell, all later ones fail.
+    FailAfter(usize),
+    /// The next call fails, later ones behave like the wrapped RCell.
+    FailOnce,
+    /// Every call fails.
+    AlwaysFail,
+}
+
+/// A RCell double whose upgrades fail as scripted, regardless of whether the value is alive.
+/// This makes the miss handling paths of downstream code deterministic to test. Failing
+/// calls leave the wrapped cell unchanged.
+#[derive(Debug)]
+pub struct MockRCell<T: ?Sized> {
+    cell: RCell<T>,
+    script: Script,
+    calls: AtomicUsize,
+    failed_once: AtomicUsize,
+}
+
+impl<T> MockRCell<T> {
+    /// Creates a MockRCell holding a strong reference to `value`.
+    pub fn new(value: T, script: Script) -> Self {
+        Self::from_rcell(RCell::new(value), script)
+    }
+}
+
+impl<T: ?Sized> MockRCell<T> {
+    /// Wraps an existing RCell.
+    pub fn from_rcell(cell: RCell<T>, script: Script) -> Self {
+        MockRCell {
+            cell,
+            script,
+            calls: AtomicUsize::new(0),
+            failed_once: AtomicUsize::new(0),
+        }
+    }
+
+    /// Replaces the script and resets the call counter.
+    pub fn set_script(&mut self, script: Script) {
+        self.script = script;
+        self.calls.store(0, Ordering::SeqCst);
+        self.failed_once.store(0, Ordering::SeqCst);
+    }
+
+    /// Returns the number of `request()` and `retain()` calls since the script was set.
+    pub fn calls(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+
+    /// Returns the wrapped RCell.
+    pub fn inner(&self) -> &RCell<T> {
+        &self.cell
+    }
+
+    /// Counts a call and returns 'true' when it has to fail.
+    fn fails(&self) -> bool {
+        let call = self.calls.fetch_add(1, Ordering::SeqCst);
+        match self.script {
+            Script::Pass => false,
+            Script::FailAfter(n) => call >= n,
+            Script::FailOnce => self.failed_once.swap(1, Ordering::SeqCst) == 0,
+            Script::AlwaysFail => true,
+        }
+    }
+
+    /// Like `RCell::request()` but fails as scripted.
+    pub fn request(&self) -> Option<Strong<T>> {
+        if self.fails() {
+            None
+        } else {
+            self.cell.request()
+        }
+    }
+
+    /// Like `RCell::retain()` but fails as scripted.
+    pub fn retain(&mut self) -> Option<Strong<T>> {
+        if self.fails() {
+            None
+        } else {
+            self.cell.retain()
+        }
+    }
+
+    /// Releases the wrapped cell, see `RCell::release()`.
+    pub fn release(&mut self) {
+        self.cell.release();
+    }
+
+    /// Removes the reference from the wrapped cell, see `RCell::remove()`.
+    pub fn remove(&mut self) {
+        self.cell.remove();
+    }
+}
+
+impl<T: ?Sized> Retainable for MockRCell<T> {
+    fn retained(&self) -> bool {
+        self.cell.retained()
+    }
+
+    fn retain(&mut self) -> bool {
+        MockRCell::retain(self).is_some()
+    }
+
+    fn release(&mut self) {
+        MockRCell::release(self);
+    }
+
+    fn remove(&mut self) {
+        MockRCell::remove(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MockRCell, Script};
+    use crate::Retainable;
+
+    #[test]
+    fn scripts() {
+        let mut cell = MockRCell::new(1, Script::FailOnce);
+        assert_eq!(cell.request(), None);
+        assert_eq!(*cell.request().unwrap(), 1);
+        assert_eq!(cell.calls(), 2);
+
+        cell.set_script(Script::AlwaysFail);
+        assert_eq!(cell.retain(), None);
+        assert!(cell.inner().retained());
+
+        cell.set_script(Script::FailAfter(1));
+        let strong = cell.request().unwrap();
+        cell.release();
+        assert!(!Retainable::retain(&mut cell));
+        assert_eq!(cell.inner().request(), Some(strong.clone()));
+
+        cell.set_script(Script::Pass);
+        assert!(Retainable::retain(&mut cell));
+    }
+}
@@ -0,0 +1,162 @@
+//! Shared timer thread keeping values alive and releasing cells at a given time.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{RCell, Strong};
+
+struct Entry {
+    deadline: Instant,
+    action: Box<dyn FnOnce() + Send>,
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.deadline == other.deadline
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.deadline.cmp(&other.deadline)
+    }
+}
+
+/// Returns the sender to the timer thread, starting it on first use.
+fn timer() -> &'static Sender<Entry> {
+    static TIMER: OnceLock<Sender<Entry>> = OnceLock::new();
+    TIMER.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel::<Entry>();
+        thread::Builder::new()
+            .name("rcell-timer".into())
+            .spawn(move || {
+                let mut pending = BinaryHeap::<Reverse<Entry>>::new();
+                loop {
+                    let now = Instant::now();
+                    while pending
+                        .peek()
+                        .is_some_and(|Reverse(entry)| entry.deadline <= now)
+                    {
+                        if let Some(Reverse(entry)) = pending.pop() {
+                            (entry.action)();
+                        }
+                    }
+                    let received = match pending.peek() {
+                        Some(Reverse(entry)) => receiver.recv_timeout(entry.deadline - now),
+                        None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                    };
+                    match received {
+                        Ok(entry) => pending.push(Reverse(entry)),
+                        Err(RecvTimeoutError::Timeout) => {}
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+            })
+            .expect("failed to spawn the rcell timer thread");
+        sender
+    })
+}
+
+/// Runs `action` on the timer thread once `duration` passed.
+fn after(duration: Duration, action: impl FnOnce() + Send + 'static) {
+    let entry = Entry {
+        deadline: Instant::now() + duration,
+        action: Box::new(action),
+    };
+    // the timer thread only goes away when the process ends
+    let _ = timer().send(entry);
+}
+
+/// Retains the shared `cell`, see `RCell::retain()`, and releases it again on a shared timer
+/// thread once `duration` passed. Until then the cell stays retained and the value is kept
+/// alive, even when the cell gets released meanwhile. The timer only holds a weak reference
+/// to the cell, dropping it earlier is fine.
+pub fn retain_for<T: ?Sized + Send + Sync + 'static>(
+    cell: &Strong<Mutex<RCell<T>>>,
+    duration: Duration,
+) -> Option<Strong<T>> {
+    let strong = cell
+        .lock()
+        .unwrap_or_else(|poison| poison.into_inner())
+        .retain()?;
+    let hold = strong.clone();
+    let cell = Strong::downgrade(cell);
+    after(duration, move || {
+        if let Some(cell) = cell.upgrade() {
+            cell.lock()
+                .unwrap_or_else(|poison| poison.into_inner())
+                .release();
+        }
+        drop(hold);
+    });
+    Some(strong)
+}
+
+impl<T: ?Sized + Send + Sync + 'static> RCell<T> {
+    /// Releases the cell, but keeps a strong reference to the value on a shared timer thread
+    /// for `duration`. The value stays alive at least that long and only becomes subject to
+    /// being dropped afterwards.
+    pub fn release_after(&mut self, duration: Duration) {
+        if let Some(strong) = self.request() {
+            after(duration, move || drop(strong));
+        }
+        self.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{self, Sender};
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use super::retain_for;
+    use crate::{RCell, Strong};
+
+    /// Reports its drop over a channel.
+    struct Dropped(Mutex<Sender<()>>);
+
+    impl Drop for Dropped {
+        fn drop(&mut self) {
+            let _ = self.0.lock().unwrap().send(());
+        }
+    }
+
+    #[test]
+    fn release_after() {
+        let (sender, dropped) = mpsc::channel();
+        let mut rcell = RCell::new(Dropped(Mutex::new(sender)));
+        rcell.release_after(Duration::from_millis(20));
+        assert!(!rcell.retained());
+        assert!(rcell.request().is_some());
+        dropped.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(rcell.request().is_none());
+    }
+
+    #[test]
+    fn retain_for_releases() {
+        let (sender, dropped) = mpsc::channel();
+        let value = Strong::new(Dropped(Mutex::new(sender)));
+        let cell = Strong::new(Mutex::new(RCell::from(Strong::downgrade(&value))));
+        assert!(retain_for(&cell, Duration::from_millis(20)).is_some());
+        drop(value);
+        assert!(cell.lock().unwrap().retained());
+        assert!(dropped.try_recv().is_err());
+        // the timer releases the cell and the value goes away with it
+        dropped.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(!cell.lock().unwrap().retained());
+        assert!(cell.lock().unwrap().request().is_none());
+    }
+}
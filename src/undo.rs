@@ -0,0 +1,132 @@
+//! Cell remembering its prior values.
+
+use std::collections::VecDeque;
+
+use crate::{RCell, Replace, Strong, Weak};
+
+/// Keeps the current value strong and the last `depth` prior values weakly. Prior values are
+/// available as long as something else keeps them alive, for example an undo buffer holding
+/// the ones that must survive or a debugger looking at what changed.
+#[derive(Debug)]
+pub struct UndoRCell<T: ?Sized> {
+    current: RCell<T>,
+    previous: VecDeque<Weak<T>>,
+    depth: usize,
+}
+
+impl<T> UndoRCell<T> {
+    /// Creates an UndoRCell remembering up to `depth` prior values.
+    pub fn new(value: T, depth: usize) -> Self {
+        Self::from_strong(Strong::new(value), depth)
+    }
+
+    /// Makes `value` the current value, see `set_strong()`.
+    pub fn set(&mut self, value: T) -> Strong<T> {
+        self.set_strong(Strong::new(value))
+    }
+}
+
+impl<T: ?Sized> UndoRCell<T> {
+    /// Creates an UndoRCell from a `Strong<T>` remembering up to `depth` prior values.
+    pub fn from_strong(strong: Strong<T>, depth: usize) -> Self {
+        UndoRCell {
+            current: RCell::from(strong),
+            previous: VecDeque::with_capacity(depth),
+            depth,
+        }
+    }
+
+    /// Makes `strong` the current value, the old current value becomes the most recent
+    /// prior value. The oldest prior value is forgotten when there are more than `depth`.
+    /// Returns the new current value.
+    pub fn set_strong(&mut self, strong: Strong<T>) -> Strong<T> {
+        if let Some(old) = self.current.request() {
+            if self.depth > 0 {
+                if self.previous.len() == self.depth {
+                    self.previous.pop_back();
+                }
+                self.previous.push_front(Strong::downgrade(&old));
+            }
+        }
+        self.current.replace(strong.clone());
+        strong
+    }
+
+    /// Returns the current value.
+    pub fn current(&self) -> Option<Strong<T>> {
+        self.current.request()
+    }
+
+    /// Returns the `i`th prior value, 0 is the most recent one. 'None' when there is no such
+    /// value or nothing kept it alive.
+    pub fn previous(&self, i: usize) -> Option<Strong<T>> {
+        crate::upgrade(self.previous.get(i)?)
+    }
+
+    /// Makes the most recent prior value current again, dropping the current one from the
+    /// history. Returns the restored value or 'None' when the prior value is gone, the cell is
+    /// left unchanged then.
+    pub fn undo(&mut self) -> Option<Strong<T>> {
+        let restored = self.previous(0)?;
+        self.previous.pop_front();
+        self.current.replace(restored.clone());
+        Some(restored)
+    }
+
+    /// Returns the number of prior values remembered, including dead ones.
+    pub fn len(&self) -> usize {
+        self.previous.len()
+    }
+
+    /// Returns 'true' when no prior values are remembered.
+    pub fn is_empty(&self) -> bool {
+        self.previous.is_empty()
+    }
+
+    /// Returns how many prior values are remembered at most.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Forgets all prior values.
+    pub fn clear(&mut self) {
+        self.previous.clear();
+    }
+
+    /// Iterates over the prior values which are still alive, most recent first.
+    pub fn iter_previous(&self) -> impl Iterator<Item = Strong<T>> + '_ {
+        self.previous.iter().filter_map(crate::upgrade)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UndoRCell;
+
+    #[test]
+    fn previous() {
+        let mut cell = UndoRCell::new(0, 2);
+        let kept: Vec<_> = (1..=3).map(|i| cell.set(i)).collect();
+        assert_eq!(*cell.current().unwrap(), 3);
+        assert_eq!(cell.len(), 2);
+        assert_eq!(*cell.previous(0).unwrap(), 2);
+        assert_eq!(*cell.previous(1).unwrap(), 1);
+        assert_eq!(cell.previous(2), None);
+
+        drop(kept);
+        // 3 is still current, 2 and 1 are gone
+        assert_eq!(cell.iter_previous().count(), 0);
+        assert_eq!(cell.undo(), None);
+        assert_eq!(*cell.current().unwrap(), 3);
+    }
+
+    #[test]
+    fn undo() {
+        let mut cell = UndoRCell::new("a", 4);
+        let a = cell.current().unwrap();
+        cell.set("b");
+        assert_eq!(cell.undo(), Some(a));
+        assert!(cell.is_empty());
+        assert_eq!(*cell.current().unwrap(), "a");
+    }
+}
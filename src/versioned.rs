@@ -0,0 +1,111 @@
+//! Cell counting the changes of its value.
+
+use std::ops::Deref;
+
+use crate::{RCell, Replace, Strong, Weak};
+
+/// A RCell with a version which increases whenever a different reference gets stored in it.
+/// Derived data can remember the version it was computed from and cheaply check whether its
+/// input changed without comparing values. Retaining and releasing don't change the version.
+/// Derefs to the inner `RCell<T>` for read only access.
+#[derive(Debug, Default)]
+pub struct VersionedRCell<T: ?Sized> {
+    cell: RCell<T>,
+    version: u64,
+}
+
+impl<T> VersionedRCell<T> {
+    /// Creates a new strong VersionedRCell at version 0.
+    pub fn new(value: T) -> Self {
+        Self::from_rcell(RCell::new(value))
+    }
+
+    /// Stores a new value, see `set_strong()`.
+    pub fn set(&mut self, value: T) -> u64 {
+        self.set_strong(Strong::new(value))
+    }
+}
+
+impl<T: ?Sized> VersionedRCell<T> {
+    /// Wraps an existing RCell at version 0.
+    pub fn from_rcell(cell: RCell<T>) -> Self {
+        VersionedRCell { cell, version: 0 }
+    }
+
+    /// Returns the current version.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Tries to get a `Strong<T>` together with the version it belongs to.
+    pub fn request_versioned(&self) -> Option<(Strong<T>, u64)> {
+        Some((self.cell.request()?, self.version))
+    }
+
+    /// Stores a new strong reference, returns the new version.
+    pub fn set_strong(&mut self, strong: Strong<T>) -> u64 {
+        self.cell.replace(strong);
+        self.bump()
+    }
+
+    /// Stores a new weak reference, returns the new version.
+    pub fn set_weak(&mut self, weak: Weak<T>) -> u64 {
+        self.cell.replace(weak);
+        self.bump()
+    }
+
+    /// Tries to retain the cell, see `RCell::retain()`.
+    pub fn retain(&mut self) -> Option<Strong<T>> {
+        self.cell.retain()
+    }
+
+    /// Releases the cell, see `RCell::release()`.
+    pub fn release(&mut self) {
+        self.cell.release();
+    }
+
+    /// Removes the reference from the cell and increases the version.
+    pub fn remove(&mut self) -> u64 {
+        self.cell.remove();
+        self.bump()
+    }
+
+    /// Unwraps the inner RCell.
+    pub fn into_inner(self) -> RCell<T> {
+        self.cell
+    }
+
+    fn bump(&mut self) -> u64 {
+        self.version += 1;
+        self.version
+    }
+}
+
+impl<T: ?Sized> Deref for VersionedRCell<T> {
+    type Target = RCell<T>;
+
+    fn deref(&self) -> &RCell<T> {
+        &self.cell
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::VersionedRCell;
+
+    #[test]
+    fn versions() {
+        let mut cell = VersionedRCell::new(1);
+        let (value, seen) = cell.request_versioned().unwrap();
+        assert_eq!((*value, seen), (1, 0));
+
+        cell.release();
+        cell.retain();
+        assert_eq!(cell.version(), seen);
+
+        assert_eq!(cell.set(2), 1);
+        assert_ne!(cell.version(), seen);
+        assert_eq!(cell.remove(), 2);
+        assert_eq!(cell.request_versioned(), None);
+    }
+}
@@ -0,0 +1,151 @@
+//! Walking the RCells embedded in arbitrary data structures.
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::RCell;
+
+/// Called for every RCell reached by `VisitCells::visit_cells()`.
+pub trait CellVisitor {
+    /// Visits a single cell.
+    fn visit<T: ?Sized>(&mut self, cell: &mut RCell<T>);
+}
+
+/// Data structures exposing the RCells they contain. With the **derive** feature
+/// `#[derive(VisitCells)]` implements this for structs, visiting all `RCell` fields and the
+/// fields annotated with `#[visit]`.
+pub trait VisitCells {
+    /// Passes every contained RCell to `visitor`.
+    fn visit_cells<V: CellVisitor + ?Sized>(&mut self, visitor: &mut V);
+}
+
+impl<T: ?Sized> VisitCells for RCell<T> {
+    fn visit_cells<V: CellVisitor + ?Sized>(&mut self, visitor: &mut V) {
+        visitor.visit(self);
+    }
+}
+
+impl<C: VisitCells + ?Sized> VisitCells for Box<C> {
+    fn visit_cells<V: CellVisitor + ?Sized>(&mut self, visitor: &mut V) {
+        (**self).visit_cells(visitor);
+    }
+}
+
+impl<C: VisitCells> VisitCells for Option<C> {
+    fn visit_cells<V: CellVisitor + ?Sized>(&mut self, visitor: &mut V) {
+        if let Some(inner) = self {
+            inner.visit_cells(visitor);
+        }
+    }
+}
+
+impl<C: VisitCells> VisitCells for [C] {
+    fn visit_cells<V: CellVisitor + ?Sized>(&mut self, visitor: &mut V) {
+        self.iter_mut().for_each(|inner| inner.visit_cells(visitor));
+    }
+}
+
+impl<C: VisitCells, const N: usize> VisitCells for [C; N] {
+    fn visit_cells<V: CellVisitor + ?Sized>(&mut self, visitor: &mut V) {
+        self.as_mut_slice().visit_cells(visitor);
+    }
+}
+
+impl<C: VisitCells> VisitCells for Vec<C> {
+    fn visit_cells<V: CellVisitor + ?Sized>(&mut self, visitor: &mut V) {
+        self.as_mut_slice().visit_cells(visitor);
+    }
+}
+
+impl<C: VisitCells> VisitCells for VecDeque<C> {
+    fn visit_cells<V: CellVisitor + ?Sized>(&mut self, visitor: &mut V) {
+        self.iter_mut().for_each(|inner| inner.visit_cells(visitor));
+    }
+}
+
+/// Visits the values, keys can't contain cells which could be mutated.
+impl<K, C: VisitCells, S> VisitCells for HashMap<K, C, S> {
+    fn visit_cells<V: CellVisitor + ?Sized>(&mut self, visitor: &mut V) {
+        self.values_mut()
+            .for_each(|inner| inner.visit_cells(visitor));
+    }
+}
+
+/// Releases every RCell reachable from `value`. Returns how many were retained before.
+pub fn release_reachable(value: &mut (impl VisitCells + ?Sized)) -> usize {
+    struct Release(usize);
+
+    impl CellVisitor for Release {
+        fn visit<T: ?Sized>(&mut self, cell: &mut RCell<T>) {
+            if cell.retained() {
+                self.0 += 1;
+                cell.release();
+            }
+        }
+    }
+
+    let mut visitor = Release(0);
+    value.visit_cells(&mut visitor);
+    visitor.0
+}
+
+/// Returns the number of retained RCells reachable from `value`.
+pub fn count_retained(value: &mut (impl VisitCells + ?Sized)) -> usize {
+    struct Count(usize);
+
+    impl CellVisitor for Count {
+        fn visit<T: ?Sized>(&mut self, cell: &mut RCell<T>) {
+            self.0 += cell.retained() as usize;
+        }
+    }
+
+    let mut visitor = Count(0);
+    value.visit_cells(&mut visitor);
+    visitor.0
+}
+
+/// Returns the summed size of the values held by retained RCells reachable from `value`.
+/// Only the shallow size (`std::mem::size_of_val()`) is counted, values shared between
+/// several cells are counted for each of them.
+pub fn retained_bytes(value: &mut (impl VisitCells + ?Sized)) -> usize {
+    struct Bytes(usize);
+
+    impl CellVisitor for Bytes {
+        fn visit<T: ?Sized>(&mut self, cell: &mut RCell<T>) {
+            if cell.retained() {
+                if let Some(strong) = cell.request() {
+                    self.0 += std::mem::size_of_val(&*strong);
+                }
+            }
+        }
+    }
+
+    let mut visitor = Bytes(0);
+    value.visit_cells(&mut visitor);
+    visitor.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{count_retained, release_reachable, retained_bytes};
+    use crate::RCell;
+
+    #[test]
+    fn nested() {
+        let mut data = vec![
+            Some(Box::new(RCell::new(1u32))),
+            None,
+            Some(Box::new(RCell::new(2u32))),
+        ];
+        assert_eq!(count_retained(&mut data), 2);
+        assert_eq!(retained_bytes(&mut data), 8);
+        assert_eq!(release_reachable(&mut data), 2);
+        assert_eq!(count_retained(&mut data), 0);
+    }
+
+    #[test]
+    fn unsized_values() {
+        let mut map = std::collections::HashMap::new();
+        map.insert("a", [RCell::<str>::from("foo"), RCell::from("quux")]);
+        assert_eq!(retained_bytes(&mut map), 7);
+    }
+}
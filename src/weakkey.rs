@@ -0,0 +1,150 @@
+//! Map with weakly held keys.
+
+use std::collections::HashMap;
+
+use crate::{Strong, Weak};
+
+fn addr<K: ?Sized>(ptr: *const K) -> usize {
+    ptr as *const () as usize
+}
+
+/// A map whose keys are held weakly, entries vanish when their key object gets dropped. Keys
+/// are compared by identity (allocation address), not by value. This is the structure for
+/// attaching metadata to externally owned objects.
+///
+/// Dead entries are skipped on lookup and iteration, their values are dropped by `purge()`
+/// which is also called whenever the map has grown to twice the size it had after the last
+/// purge.
+#[derive(Debug)]
+pub struct WeakKeyMap<K: ?Sized, V> {
+    entries: HashMap<usize, (Weak<K>, V)>,
+    purge_at: usize,
+}
+
+impl<K: ?Sized, V> WeakKeyMap<K, V> {
+    /// Creates an empty map.
+    pub fn new() -> Self {
+        WeakKeyMap {
+            entries: HashMap::new(),
+            purge_at: 16,
+        }
+    }
+
+    fn live_entry(&self, key: &Strong<K>) -> Option<&(Weak<K>, V)> {
+        self.entries
+            .get(&addr(Strong::as_ptr(key)))
+            .filter(|(weak, _)| weak.strong_count() > 0)
+    }
+
+    /// Inserts a value for `key`, returns the previous value of a live entry.
+    pub fn insert(&mut self, key: &Strong<K>, value: V) -> Option<V> {
+        let old = self
+            .entries
+            .insert(addr(Strong::as_ptr(key)), (Strong::downgrade(key), value));
+        if self.entries.len() >= self.purge_at {
+            self.purge();
+        }
+        // a dead entry at the same address belonged to a different key
+        old.filter(|(weak, _)| weak.strong_count() > 0)
+            .map(|(_, value)| value)
+    }
+
+    /// Returns the value for `key`.
+    pub fn get(&self, key: &Strong<K>) -> Option<&V> {
+        self.live_entry(key).map(|(_, value)| value)
+    }
+
+    /// Returns the value for `key` mutably.
+    pub fn get_mut(&mut self, key: &Strong<K>) -> Option<&mut V> {
+        self.entries
+            .get_mut(&addr(Strong::as_ptr(key)))
+            .filter(|(weak, _)| weak.strong_count() > 0)
+            .map(|(_, value)| value)
+    }
+
+    /// Returns 'true' when there is a value for `key`.
+    pub fn contains_key(&self, key: &Strong<K>) -> bool {
+        self.live_entry(key).is_some()
+    }
+
+    /// Removes the entry for `key` and returns its value.
+    pub fn remove(&mut self, key: &Strong<K>) -> Option<V> {
+        self.live_entry(key)?;
+        self.entries
+            .remove(&addr(Strong::as_ptr(key)))
+            .map(|(_, value)| value)
+    }
+
+    /// Returns the number of live entries.
+    pub fn len(&self) -> usize {
+        self.entries
+            .values()
+            .filter(|(weak, _)| weak.strong_count() > 0)
+            .count()
+    }
+
+    /// Returns 'true' when there are no live entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drops all entries whose key is gone. Returns the number of entries removed.
+    pub fn purge(&mut self) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|_, (weak, _)| weak.strong_count() > 0);
+        self.purge_at = (self.entries.len() * 2).max(16);
+        before - self.entries.len()
+    }
+
+    /// Iterates over the live entries.
+    pub fn iter(&self) -> impl Iterator<Item = (Strong<K>, &V)> + '_ {
+        self.entries
+            .values()
+            .filter_map(|(weak, value)| Some((weak.upgrade()?, value)))
+    }
+}
+
+impl<K: ?Sized, V> Default for WeakKeyMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WeakKeyMap;
+    use crate::Strong;
+
+    #[test]
+    fn weak_keys() {
+        let mut map = WeakKeyMap::new();
+        let first = Strong::new(String::from("foo"));
+        let second = Strong::new(String::from("foo"));
+        assert_eq!(map.insert(&first, 1), None);
+        assert_eq!(map.insert(&second, 2), None);
+        assert_eq!(map.insert(&first, 3), Some(1));
+        assert_eq!(map.get(&first), Some(&3));
+        assert_eq!(map.get(&second), Some(&2));
+        *map.get_mut(&second).unwrap() += 1;
+        assert_eq!(map.len(), 2);
+
+        drop(second);
+        assert_eq!(map.len(), 1);
+        assert_eq!(
+            map.iter().map(|(_, value)| *value).collect::<Vec<_>>(),
+            vec![3]
+        );
+        assert_eq!(map.purge(), 1);
+        assert_eq!(map.remove(&first), Some(3));
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn unsized_keys() {
+        let mut map = WeakKeyMap::<str, u8>::default();
+        let key: Strong<str> = Strong::from("foo");
+        map.insert(&key, 1);
+        assert!(map.contains_key(&key));
+        assert!(!map.contains_key(&Strong::from("foo")));
+    }
+}
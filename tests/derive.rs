@@ -0,0 +1,155 @@
+#![cfg(feature = "derive")]
+use rcell::*;
+
+#[derive(Release, Retain)]
+struct Cached<T: Clone> {
+    first: RCell<T>,
+    second: rcell::RCell<Vec<T>>,
+    untouched: Strong<T>,
+}
+
+#[derive(Release, Retain)]
+struct Tuple(RCell<u32>, u32);
+
+#[test]
+fn release_all() {
+    let keep = Strong::new(vec![1u8]);
+    let mut cached = Cached {
+        first: RCell::new(1u8),
+        second: RCell::from(keep.clone()),
+        untouched: Strong::new(2),
+    };
+    cached.release_all();
+    assert!(!cached.first.retained());
+    assert_eq!(cached.first.request(), None);
+    assert!(!cached.second.retained());
+    assert_eq!(*cached.second.request().unwrap(), vec![1]);
+    assert_eq!(*cached.untouched, 2);
+}
+
+#[test]
+fn remove_all() {
+    let mut tuple = Tuple(RCell::new(1), 2);
+    tuple.remove_all();
+    assert_eq!(tuple.0.request(), None);
+    assert_eq!(tuple.1, 2);
+}
+
+#[test]
+fn retain_all() {
+    let keep = Strong::new(vec![1u8]);
+    let mut cached = Cached {
+        first: RCell::new(1u8),
+        second: RCell::from(keep.clone()),
+        untouched: Strong::new(2),
+    };
+    cached.release_all();
+    assert!(!cached.retain_all());
+    assert!(!cached.first.retained());
+    assert!(cached.second.retained());
+    assert_eq!(cached.retain_all_failed(), vec!["first"]);
+
+    cached.first = RCell::new(3);
+    assert!(cached.retain_all());
+}
+
+#[test]
+fn retain_all_tuple() {
+    let mut tuple = Tuple(RCell::new(1), 2);
+    tuple.release_all();
+    assert_eq!(tuple.retain_all_failed(), vec!["0"]);
+}
+
+#[derive(Accessors)]
+struct Member<T> {
+    #[rcell]
+    name: RCell<String>,
+    #[rcell]
+    pub value: RCell<Vec<T>>,
+    other: RCell<u8>,
+}
+
+#[test]
+fn accessors() {
+    let mut member = Member::<u8> {
+        name: RCell::default(),
+        value: RCell::default(),
+        other: RCell::new(1),
+    };
+    assert_eq!(member.get_name(), None);
+    member.set_name("foo".into());
+    member.set_value(vec![1, 2]);
+    assert_eq!(*member.get_name().unwrap(), "foo");
+    let value = member.get_value().unwrap();
+    member.release_value();
+    member.release_name();
+    assert_eq!(member.get_name(), None);
+    assert_eq!(*member.get_value().unwrap(), vec![1, 2]);
+    drop(value);
+    assert_eq!(member.get_value(), None);
+    assert!(member.other.retained());
+}
+
+static CALLS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+#[rcell::cached]
+fn load(name: &str, version: u32) -> Strong<String> {
+    CALLS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    Strong::new(format!("{name}-{version}"))
+}
+
+#[test]
+fn cached() {
+    let calls = || CALLS.load(std::sync::atomic::Ordering::SeqCst);
+    let first = load("foo", 1);
+    assert_eq!(*first, "foo-1");
+    let again = load("foo", 1);
+    assert!(Strong::ptr_eq(&first, &again));
+    assert_eq!(calls(), 1);
+    let _other = load("foo", 2);
+    assert_eq!(calls(), 2);
+    drop(first);
+    drop(again);
+    assert_eq!(*load("foo", 1), "foo-1");
+    assert_eq!(calls(), 3);
+}
+
+#[derive(VisitCells)]
+struct Document {
+    title: RCell<String>,
+    #[visit]
+    pages: Vec<RCell<Vec<u8>>>,
+    #[visit]
+    cover: Option<Member<u8>>,
+    size: usize,
+}
+
+#[derive(VisitCells)]
+struct Chain<T>(RCell<T>, #[visit] Option<Box<Chain<T>>>);
+
+impl VisitCells for Member<u8> {
+    fn visit_cells<V: CellVisitor + ?Sized>(&mut self, visitor: &mut V) {
+        visitor.visit(&mut self.name);
+    }
+}
+
+#[test]
+fn visit_cells() {
+    let mut document = Document {
+        title: RCell::new("title".into()),
+        pages: vec![RCell::new(vec![1; 4]), RCell::new(vec![2; 4])],
+        cover: Some(Member {
+            name: RCell::new("cover".into()),
+            value: RCell::default(),
+            other: RCell::new(1),
+        }),
+        size: 8,
+    };
+    assert_eq!(document.size, 8);
+    assert_eq!(count_retained(&mut document), 4);
+    assert_eq!(release_reachable(&mut document), 4);
+    assert!(document.cover.unwrap().other.retained());
+
+    let mut nested = Chain(RCell::new(1), Some(Box::new(Chain(RCell::new(2), None))));
+    assert_eq!(retained_bytes(&mut nested), 8);
+}
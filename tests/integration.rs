@@ -11,4 +11,5 @@ fn own_type() {
     struct MyType(&'static str);
     let rcell = RCell::new(MyType("foobar"));
     assert!(rcell.retained());
+    assert_eq!(rcell.request().unwrap().0, "foobar");
 }